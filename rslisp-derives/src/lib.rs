@@ -1,76 +1,375 @@
-#![feature(proc_macro_diagnostic)]
-
 extern crate proc_macro;
 
-use im::Vector;
-use rslisp::types::{BuiltinFunction, BuiltinMacro, Scope, Type};
+mod record;
 
-use proc_macro::{TokenStream, TokenTree};
-use quote::{quote, quote_spanned};
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::{
+    DeriveInput, Expr, ExprLit, FnArg, Ident, ItemFn, Lit, LitStr, Meta, PathArguments, Token, Type,
+};
 
+/// Turn a function into a builtin: `#[builtin(fn)]` or `#[builtin(macro)]`
+/// on `fn name(...)` adds `fn name_builtin()`, which builds the
+/// `BuiltinFunction` or `BuiltinMacro`, and submits it to rslisp's registry
+/// so every new environment binds it.
+///
+/// The builtin is called `name` unless the attribute gives `name = "..."`,
+/// and each `alias = "..."` adds another name for it. The function's doc
+/// comment becomes the builtin's docstring.
+///
+/// A function may take the raw arguments, as `(args: Vector<Type>, scope:
+/// &mut Scope)`, or typed parameters. Typed parameters are converted with
+/// `FromLisp`: an `Option<T>` parameter is optional, a trailing `Rest<T>`
+/// collects the remaining arguments, and a `&mut Scope` parameter gets the
+/// calling scope. The result is converted with `IntoLisp`, and may also be a
+/// `Result<_, Error>`.
 #[proc_macro_attribute]
 pub fn builtin(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let builtin_type = match attr.into_iter().next() {
-        Some(TokenTree::Ident(id)) => {
-            let kind = id.to_string();
-
-            if kind == "fn" {
-                quote!(BuiltinFunction)
-            } else if kind == "macro" {
-                quote!(BuiltinMacro)
-            } else {
-                panic!("Attribute arguments may only be `macro` or `fn`");
-            }
-        }
-        Some(t) => {
-            t.span().error("Attribute must be an identifier").emit();
-            panic!()
-        }
-        _ => panic!("Must have proper arguments"),
-    };
-
-    let input = syn::parse_macro_input!(item as syn::ItemFn);
+    let args = syn::parse_macro_input!(attr as Args);
+    let input = syn::parse_macro_input!(item as ItemFn);
 
-    let name = &input.ident;
-    let name_str: String = input.ident.to_string();
+    let (builtin_type, register) = match args.kind {
+        Kind::Fn => (
+            quote!(rslisp::types::BuiltinFunction),
+            quote!(rslisp::registry::Registration::for_function),
+        ),
+        Kind::Macro => (
+            quote!(rslisp::types::BuiltinMacro),
+            quote!(rslisp::registry::Registration::for_macro),
+        ),
+    };
 
-    let block = &input.block;
+    let name = &input.sig.ident;
+    let name_str = match &args.name {
+        Some(lit) => lit.value(),
+        None => name.to_string(),
+    };
+    let aliases = &args.aliases;
+    let doc = docstring(&input).map(|doc| quote!(.with_doc(#doc)));
 
     let concat = format!("{}_builtin", name);
-    let name_builtin: syn::Ident = syn::Ident::new(&concat, name.span());
+    let name_builtin: Ident = Ident::new(&concat, name.span());
+
+    let body = if is_raw(&input) {
+        quote!(rslisp::convert::IntoLispResult::into_lisp_result(#name(args, scope), #name_str))
+    } else {
+        match typed_call(&input, &name_str) {
+            Ok(body) => body,
+            Err(err) => return err.to_compile_error().into(),
+        }
+    };
 
     let result = quote! {
         #input
 
+        #[allow(unused_mut, unused_variables)]
         pub fn #name_builtin() -> #builtin_type {
-            let fun = |args: Vector<Type>, scope: &mut Scope| -> Type {
-                #block
-            };
+            #builtin_type::new(String::from(#name_str), |args, scope| { #body })
+                #(.with_alias(#aliases))*
+                #doc
+        }
 
-            #builtin_type::new(String::from(#name_str), fun)
+        rslisp::inventory::submit! {
+            #register(module_path!(), #name_builtin)
         }
     };
 
     result.into()
 }
 
-// #[cfg(test)]
-// mod test {
-//     #[macro_use]
-//     extern crate rslisp_derives;
-
-//     // use rslisp_derives::builtin;
-//     use rslisp::{Type, Scope};
-//     use im::Vector;
-
-//     #[builtin]
-//     pub fn add(args: Vector<Type>, scope: &mut Scope) -> Type {
-//         Type::Nil
-//     }
-
-//     #[test]
-//     fn it_works() {
-//         42;
-//         ()
-//     }
-// }
+/// Give a struct with named fields a record type: `struct Point { x, y }`
+/// gets the builtins `make-point`, `point?`, `point-x` and `set-point-x!`
+/// (registered like a `#[builtin]`), and converts to and from its records
+/// with `FromLisp` and `IntoLisp`.
+///
+/// The Lisp names are the Rust ones in kebab case, so `BankAccount` is a
+/// `bank-account`. Every field has to be `FromLisp + IntoLisp`.
+#[proc_macro_derive(LispRecord)]
+pub fn derive_lisp_record(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+
+    match record::derive(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+enum Kind {
+    Fn,
+    Macro,
+}
+
+/// The attribute's arguments: `fn` or `macro`, then any `name = "..."` and
+/// `alias = "..."` pairs.
+struct Args {
+    kind: Kind,
+    name: Option<LitStr>,
+    aliases: Vec<LitStr>,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Args> {
+        let kind = if input.peek(Token![fn]) {
+            input.parse::<Token![fn]>()?;
+            Kind::Fn
+        } else if input.peek(Token![macro]) {
+            input.parse::<Token![macro]>()?;
+            Kind::Macro
+        } else {
+            return Err(input.error("expected `fn` or `macro`"));
+        };
+
+        let mut args = Args {
+            kind,
+            name: None,
+            aliases: Vec::new(),
+        };
+
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+
+            if input.is_empty() {
+                break;
+            }
+
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let value: LitStr = input.parse()?;
+
+            if key == "name" {
+                if args.name.is_some() {
+                    return Err(syn::Error::new(key.span(), "`name` given more than once"));
+                }
+                args.name = Some(value);
+            } else if key == "alias" {
+                args.aliases.push(value);
+            } else {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "unknown argument, expected `name` or `alias`",
+                ));
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+/// The lines of `fun`'s doc comment, if it has one.
+fn docstring(fun: &ItemFn) -> Option<String> {
+    let lines: Vec<String> = fun
+        .attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(doc) if doc.path.is_ident("doc") => match &doc.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(line),
+                    ..
+                }) => Some(line.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|line| {
+            line.strip_prefix(' ')
+                .unwrap_or(&line)
+                .trim_end()
+                .to_string()
+        })
+        .collect();
+
+    let doc = lines.join("\n").trim().to_string();
+
+    if doc.is_empty() {
+        None
+    } else {
+        Some(doc)
+    }
+}
+
+/// How a typed parameter is filled in.
+enum Param {
+    Required,
+    Optional,
+    Rest,
+    Scope,
+}
+
+/// The last segment of a type path, and its angle bracketed arguments.
+fn last_segment(ty: &Type) -> Option<(String, bool)> {
+    match ty {
+        Type::Path(path) => path.path.segments.last().map(|seg| {
+            let generic = matches!(seg.arguments, PathArguments::AngleBracketed(_));
+            (seg.ident.to_string(), generic)
+        }),
+        _ => None,
+    }
+}
+
+fn is_scope(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(reference) => {
+            reference.mutability.is_some()
+                && last_segment(&reference.elem).map(|(name, _)| name) == Some("Scope".into())
+        }
+        _ => false,
+    }
+}
+
+/// Whether `fun` takes the raw `(args: Vector<Type>, scope: &mut Scope)`.
+fn is_raw(fun: &ItemFn) -> bool {
+    let types: Vec<&Type> = fun
+        .sig
+        .inputs
+        .iter()
+        .filter_map(|arg| match arg {
+            FnArg::Typed(typed) => Some(&*typed.ty),
+            FnArg::Receiver(_) => None,
+        })
+        .collect();
+
+    types.len() == 2
+        && last_segment(types[0]) == Some(("Vector".into(), true))
+        && is_scope(types[1])
+}
+
+fn classify(ty: &Type) -> Param {
+    if is_scope(ty) {
+        return Param::Scope;
+    }
+
+    match last_segment(ty) {
+        Some((name, true)) if name == "Option" => Param::Optional,
+        Some((name, true)) if name == "Rest" => Param::Rest,
+        _ => Param::Required,
+    }
+}
+
+/// Unpack the arguments into `fun`'s typed parameters, call it, and convert
+/// the result.
+fn typed_call(fun: &ItemFn, name_str: &str) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &fun.sig.ident;
+
+    let mut min = 0usize;
+    let mut optional = 0usize;
+    let mut rest = false;
+    let mut unpack = Vec::new();
+    let mut call = Vec::new();
+
+    for (i, arg) in fun.sig.inputs.iter().enumerate() {
+        let typed = match arg {
+            FnArg::Typed(typed) => typed,
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(
+                    receiver,
+                    "builtins can't take `self`",
+                ))
+            }
+        };
+
+        if rest {
+            return Err(syn::Error::new_spanned(
+                typed,
+                "`Rest` must be the last parameter",
+            ));
+        }
+
+        let var = format_ident!("arg{}", i, span = Span::mixed_site());
+
+        let value = match classify(&typed.ty) {
+            Param::Scope => {
+                call.push(quote!(scope));
+                continue;
+            }
+            Param::Required if optional > 0 => {
+                return Err(syn::Error::new_spanned(
+                    typed,
+                    "required parameters can't follow optional ones",
+                ))
+            }
+            Param::Required => {
+                min += 1;
+                quote!(rslisp::convert::argument(#name_str, args.next().unwrap())?)
+            }
+            Param::Optional => {
+                optional += 1;
+                quote! {
+                    match args.next() {
+                        Some(value) => Some(rslisp::convert::argument(#name_str, value)?),
+                        None => None,
+                    }
+                }
+            }
+            Param::Rest => {
+                rest = true;
+                quote! {
+                    rslisp::convert::Rest(
+                        args.by_ref()
+                            .map(|value| rslisp::convert::argument(#name_str, value))
+                            .collect::<Result<Vec<_>, rslisp::error::Error>>()?,
+                    )
+                }
+            }
+        };
+
+        unpack.push(quote!(let #var = #value;));
+        call.push(quote!(#var));
+    }
+
+    let max = if rest {
+        quote!(None)
+    } else {
+        let max = min + optional;
+        quote!(Some(#max))
+    };
+
+    let call =
+        quote!(rslisp::convert::IntoLispResult::into_lisp_result(#name(#(#call),*), #name_str));
+
+    Ok(quote! {
+        rslisp::builtins::expect_arg_range(#name_str, &args, #min, #max)?;
+
+        let mut args = args.into_iter();
+        #(#unpack)*
+
+        #call
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(attr: &str) -> String {
+        match syn::parse_str::<Args>(attr) {
+            Ok(_) => panic!("{} parsed", attr),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_args() {
+        let args: Args = syn::parse_str(r#"fn, name = "+", alias = "add", alias = "sum""#).unwrap();
+
+        assert!(matches!(args.kind, Kind::Fn));
+        assert_eq!(args.name.unwrap().value(), "+");
+        assert_eq!(args.aliases.len(), 2);
+        assert!(syn::parse_str::<Args>("macro,").is_ok());
+    }
+
+    #[test]
+    fn reject_bad_args() {
+        assert_eq!(error("struct"), "expected `fn` or `macro`");
+        assert_eq!(
+            error(r#"fn, colour = "red""#),
+            "unknown argument, expected `name` or `alias`"
+        );
+        assert_eq!(
+            error(r#"fn, name = "a", name = "b""#),
+            "`name` given more than once"
+        );
+        assert!(error("fn, name = plus").contains("expected string literal"));
+    }
+}
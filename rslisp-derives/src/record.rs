@@ -0,0 +1,208 @@
+//! `#[derive(LispRecord)]`: a record type for a struct with named fields.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Fields, Ident};
+
+pub fn derive(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "LispRecord can't be derived for generic types",
+        ));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            fields => {
+                return Err(syn::Error::new_spanned(
+                    fields,
+                    "LispRecord needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "LispRecord can only be derived for structs",
+            ))
+        }
+    };
+
+    let name = kebab_case(&ident.to_string());
+    let expected = format!("a {}", name);
+
+    let idents: Vec<&Ident> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let types: Vec<&syn::Type> = fields.iter().map(|f| &f.ty).collect();
+    let names: Vec<String> = idents
+        .iter()
+        .map(|ident| ident.to_string().trim_start_matches("r#").replace('_', "-"))
+        .collect();
+    let indices: Vec<usize> = (0..fields.len()).collect();
+    let arity = fields.len();
+
+    let make = format!("make-{}", name);
+    let predicate = format!("{}?", name);
+
+    let accessors = names.iter().zip(&indices).map(|(field, index)| {
+        let accessor = format!("{}-{}", name, field);
+
+        quote! {
+            rslisp::types::BuiltinFunction::new(#accessor.into(), |args, _scope| {
+                rslisp::builtins::expect_args(#accessor, &args, 1)?;
+                let record = rslisp::record::instance(#accessor, &Self::record_type(), &args[0])?;
+
+                Ok(record.get(#index))
+            })
+        }
+    });
+
+    let modifiers = names.iter().zip(&indices).zip(&types).map(|((field, index), ty)| {
+        let modifier = format!("set-{}-{}!", name, field);
+
+        quote! {
+            rslisp::types::BuiltinFunction::new(#modifier.into(), |args, _scope| {
+                rslisp::builtins::expect_args(#modifier, &args, 2)?;
+                let record = rslisp::record::instance(#modifier, &Self::record_type(), &args[0])?;
+                let value: #ty = rslisp::convert::argument(#modifier, args[1].clone())?;
+                record.set(#index, rslisp::convert::IntoLispResult::into_lisp_result(value, #modifier)?);
+
+                Ok(rslisp::types::Type::Nil)
+            })
+        }
+    });
+
+    let vars: Vec<Ident> = indices
+        .iter()
+        .map(|i| format_ident!("field{}", i))
+        .collect();
+
+    Ok(quote! {
+        impl rslisp::record::LispRecord for #ident {
+            fn record_type() -> std::rc::Rc<rslisp::record::RecordType> {
+                thread_local! {
+                    static RECORD_TYPE: std::rc::Rc<rslisp::record::RecordType> = std::rc::Rc::new(
+                        rslisp::record::RecordType::new(#name, &[#(#names),*]),
+                    );
+                }
+
+                RECORD_TYPE.with(std::rc::Rc::clone)
+            }
+
+            fn builtins() -> Vec<rslisp::types::BuiltinFunction> {
+                use rslisp::record::LispRecord;
+
+                vec![
+                    rslisp::types::BuiltinFunction::new(#make.into(), |args, _scope| {
+                        rslisp::builtins::expect_args(#make, &args, #arity)?;
+                        let mut args = args.into_iter();
+                        #(let #vars: #types = rslisp::convert::argument(#make, args.next().unwrap())?;)*
+
+                        rslisp::convert::IntoLispResult::into_lisp_result(#ident { #(#idents: #vars),* }, #make)
+                    }),
+                    rslisp::types::BuiltinFunction::new(#predicate.into(), |args, _scope| {
+                        rslisp::builtins::expect_args(#predicate, &args, 1)?;
+
+                        Ok(rslisp::types::Type::Bool(matches!(
+                            &args[0],
+                            rslisp::types::Type::Record(record) if record.is(&Self::record_type())
+                        )))
+                    }),
+                    #(#accessors,)*
+                    #(#modifiers,)*
+                ]
+            }
+        }
+
+        impl rslisp::FromLisp for #ident {
+            fn from_lisp(value: rslisp::types::Type) -> Result<#ident, rslisp::error::Error> {
+                use rslisp::record::LispRecord;
+
+                match &value {
+                    rslisp::types::Type::Record(record) if record.is(&Self::record_type()) => {
+                        Ok(#ident {
+                            #(#idents: rslisp::FromLisp::from_lisp(record.get(#indices))?,)*
+                        })
+                    }
+                    _ => Err(rslisp::error::Error::Conversion {
+                        expected: #expected,
+                        got: value,
+                    }),
+                }
+            }
+        }
+
+        impl rslisp::IntoLisp for #ident {
+            fn into_lisp(self) -> Result<rslisp::types::Type, rslisp::error::Error> {
+                use rslisp::record::LispRecord;
+
+                let fields = vec![#(rslisp::IntoLisp::into_lisp(self.#idents)?),*];
+                Ok(rslisp::types::Type::Record(rslisp::record::Record::new(Self::record_type(), fields)))
+            }
+        }
+
+        rslisp::inventory::submit! {
+            rslisp::registry::Registration::for_functions(
+                module_path!(),
+                <#ident as rslisp::record::LispRecord>::builtins,
+            )
+        }
+    })
+}
+
+/// `BankAccount` as `bank-account`.
+fn kebab_case(name: &str) -> String {
+    let mut kebab = String::new();
+
+    for (i, ch) in name.trim_start_matches("r#").chars().enumerate() {
+        if ch.is_uppercase() {
+            if i > 0 {
+                kebab.push('-');
+            }
+            kebab.extend(ch.to_lowercase());
+        } else if ch == '_' {
+            kebab.push('-');
+        } else {
+            kebab.push(ch);
+        }
+    }
+
+    kebab
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lisp_names() {
+        assert_eq!(kebab_case("Point"), "point");
+        assert_eq!(kebab_case("BankAccount"), "bank-account");
+        assert_eq!(kebab_case("snake_case"), "snake-case");
+    }
+
+    #[test]
+    fn reject_unsupported() {
+        let error = |code: &str| {
+            derive(syn::parse_str(code).unwrap())
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            error("struct Pair(i64, i64);"),
+            "LispRecord needs a struct with named fields"
+        );
+        assert_eq!(
+            error("enum Shape { Circle }"),
+            "LispRecord can only be derived for structs"
+        );
+        assert_eq!(
+            error("struct Wrapper<T> { inner: T }"),
+            "LispRecord can't be derived for generic types"
+        );
+    }
+}
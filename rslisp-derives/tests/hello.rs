@@ -1,5 +1,5 @@
 use im::Vector;
-use rslisp::types::{BuiltinFunction, BuiltinMacro, Scope, Type};
+use rslisp::types::{Scope, Type};
 use rslisp_derive::builtin;
 
 #[builtin(fn)]
@@ -0,0 +1,78 @@
+use rslisp::{FromLisp, Interpreter, IntoLisp, LispRecord, Value};
+
+#[derive(Debug, Clone, PartialEq, LispRecord)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, LispRecord)]
+struct BankAccount {
+    owner: String,
+    balance_cents: i64,
+}
+
+#[test]
+fn builtins() {
+    let mut interp = Interpreter::new();
+
+    let code = "
+        (define p (make-point 1 2.5))
+        (set-point-x! p 3)
+        (print (point? p) (point? 3) (point-x p) (point-y p))
+        p";
+
+    let p = interp.eval_str(code).unwrap();
+    assert_eq!(Point::from_lisp(p.clone()), Ok(Point { x: 3.0, y: 2.5 }));
+    assert_eq!(p.to_string(), "#<point x: 3.0 y: 2.5>");
+
+    let account = interp.eval_str("(make-bank-account \"Ada\" 100)").unwrap();
+    assert_eq!(
+        format!("{:#}", account),
+        "#<bank-account owner: \"Ada\" balance-cents: 100>"
+    );
+    assert_eq!(
+        interp.eval_str("(bank-account-balance-cents (make-bank-account \"Ada\" 100))"),
+        Ok(Value::Int(100))
+    );
+}
+
+#[test]
+fn conversions() {
+    let mut interp = Interpreter::new();
+    let point = Point { x: 1.0, y: -1.0 };
+
+    interp.define_global("origin", point.clone().into_lisp().unwrap());
+    assert_eq!(interp.eval_str("(point-y origin)"), Ok(Value::Float(-1.0)));
+    assert_eq!(interp.eval_str("(point? origin)"), Ok(Value::Bool(true)));
+    assert_eq!(
+        Point::from_lisp(interp.get_global("origin").unwrap()),
+        Ok(point)
+    );
+
+    let account = BankAccount {
+        owner: "Ada".into(),
+        balance_cents: 5,
+    };
+    assert!(Point::from_lisp(account.into_lisp().unwrap()).is_err());
+}
+
+#[test]
+fn errors() {
+    let mut interp = Interpreter::new();
+    let mut message = |code: &str| interp.eval_str(code).unwrap_err().to_string();
+
+    assert_eq!(
+        message("(make-point 1)"),
+        "make-point: expected 2 arguments, got 1"
+    );
+    assert_eq!(
+        message("(make-point 1 \"y\")"),
+        "make-point: expected a number, got \"y\""
+    );
+    assert_eq!(message("(point-x 5)"), "point-x: expected a point, got 5");
+    assert_eq!(
+        message("(set-point-y! (make-point 1 2) \"y\")"),
+        "set-point-y!: expected a number, got \"y\""
+    );
+}
@@ -0,0 +1,112 @@
+use rslisp::{Error, Interpreter, Rest, Value};
+use rslisp_derive::builtin;
+
+#[builtin(fn)]
+fn clamp(x: f64, lo: f64, hi: f64) -> f64 {
+    x.max(lo).min(hi)
+}
+
+#[builtin(fn)]
+fn greet(name: String, greeting: Option<String>) -> String {
+    format!("{}, {}!", greeting.as_deref().unwrap_or("Hello"), name)
+}
+
+#[builtin(fn)]
+fn sum(first: i64, rest: Rest<i64>) -> i64 {
+    first + rest.iter().sum::<i64>()
+}
+
+#[builtin(fn, name = "checked-div")]
+fn checked_div(a: i64, b: i64) -> Result<i64, Error> {
+    a.checked_div(b)
+        .ok_or_else(|| Error::Message("checked-div: division by zero".into()))
+}
+
+/// The builtins above register themselves, so the standard environment
+/// already has them.
+fn interpreter() -> Interpreter {
+    Interpreter::with_stdlib()
+}
+
+#[test]
+fn converts_arguments_and_result() {
+    let mut interp = interpreter();
+
+    assert_eq!(interp.eval_str("(clamp 12 0 10)"), Ok(Value::Float(10.0)));
+    assert_eq!(interp.eval_str("(checked-div 7 2)"), Ok(Value::Int(3)));
+    assert_eq!(
+        interp.eval_str("(checked-div 7 0)"),
+        Err(Error::Message("checked-div: division by zero".into()))
+    );
+}
+
+#[test]
+fn optional_and_rest() {
+    let mut interp = interpreter();
+
+    assert_eq!(
+        interp.eval_str("(greet \"Ada\")"),
+        Ok(Value::StrLit("Hello, Ada!".into()))
+    );
+    assert_eq!(
+        interp.eval_str("(greet \"Ada\" \"Hi\")"),
+        Ok(Value::StrLit("Hi, Ada!".into()))
+    );
+    assert_eq!(interp.eval_str("(sum 1)"), Ok(Value::Int(1)));
+    assert_eq!(interp.eval_str("(sum 1 2 3 4)"), Ok(Value::Int(10)));
+}
+
+#[test]
+fn reports_arity_and_types() {
+    let mut interp = interpreter();
+
+    let mut message = |code: &str| interp.eval_str(code).unwrap_err().to_string();
+
+    assert_eq!(message("(clamp 1 2)"), "clamp: expected 3 arguments, got 2");
+    assert_eq!(
+        message("(greet)"),
+        "greet: expected 1 to 2 arguments, got 0"
+    );
+    assert_eq!(
+        message("(sum)"),
+        "sum: expected at least 1 arguments, got 0"
+    );
+    assert_eq!(
+        message("(sum 1 \"two\")"),
+        "sum: expected an integer that fits in i64, got \"two\""
+    );
+}
+
+/// Add numbers.
+///
+/// Takes any number of integers.
+#[builtin(fn, name = "+", alias = "add")]
+fn plus(nums: Rest<i64>) -> i64 {
+    nums.iter().sum()
+}
+
+#[test]
+fn names_aliases_and_docs() {
+    let plus = plus_builtin();
+
+    assert_eq!(plus.name(), "+");
+    assert_eq!(plus.aliases(), ["add".to_string()]);
+    assert_eq!(
+        plus.doc(),
+        Some("Add numbers.\n\nTakes any number of integers.")
+    );
+    assert_eq!(clamp_builtin().doc(), None);
+
+    let mut interp = interpreter();
+
+    assert_eq!(
+        interp.eval_str("(doc add)"),
+        Ok(Value::StrLit(
+            "Add numbers.\n\nTakes any number of integers.".into()
+        ))
+    );
+    assert_eq!(
+        interp.eval_str("(add 1 \"2\")").unwrap_err().to_string(),
+        "+: expected an integer that fits in i64, got \"2\""
+    );
+}
@@ -1,33 +1,172 @@
 use rslisp::{
-    eval::{create_env, eval},
-    lexer::Lexer,
-    parser::parse,
-    types::Type,
+    editor::LineHelper,
+    repl::{Repl, Reply},
+    Backend, Error, Interpreter, Value,
 };
 
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "\
+usage: rslisp [--vm]                    start the REPL, or run a program piped to stdin
+       rslisp [--vm] FILE [ARGS...]     run FILE, with ARGS bound to *argv*
+       rslisp [--vm] -e EXPR [ARGS...]  run the expressions in EXPR
+       rslisp [--vm] - [ARGS...]        run the program read from stdin
+
+--vm compiles to bytecode instead of walking the tree.";
+
 fn main() {
-    let code = "(define (add-new x y) (+ x y)) (add-new 1 1)";
-    // let code = "(+ 1 1)";
+    let mut args = env::args().skip(1).peekable();
 
-    let mut lexer = Lexer::new(code);
+    let backend = if args.peek().map(String::as_str) == Some("--vm") {
+        args.next();
+        Backend::Bytecode
+    } else {
+        Backend::TreeWalk
+    };
+    let run = |name: &str, source, argv| run(backend, name, source, argv);
 
-    let mut sexprs = parse(&mut lexer).collect::<Vec<Type>>().into_iter();
+    let status = match args.next() {
+        None if io::stdin().is_terminal() => repl(backend),
+        None => run("<stdin>", read_stdin(), Vec::new()),
+        Some(flag) if flag == "-h" || flag == "--help" => {
+            println!("{}", USAGE);
+            0
+        }
+        Some(flag) if flag == "-e" => match args.next() {
+            Some(expr) => run("-e", Ok(expr), args.collect()),
+            None => usage("-e needs an expression"),
+        },
+        Some(flag) if flag == "-" => run("<stdin>", read_stdin(), args.collect()),
+        Some(flag) if flag.starts_with('-') => usage(&format!("unknown option `{}`", flag)),
+        Some(path) => {
+            let source = fs::read_to_string(&path);
+            run(&path, source, args.collect())
+        }
+    };
 
-    let mut env = create_env();
+    process::exit(status);
+}
 
-    // println!("code: {}", code);
-    // println!("Sexprs: {:?}", sexprs);
-    // println!("Env: {:?}", env);
+fn usage(problem: &str) -> i32 {
+    eprintln!("rslisp: {}\n{}", problem, USAGE);
+    2
+}
 
-    while let Some(t) = sexprs.next() {
-        let ans = eval(t, &mut env);
+fn read_stdin() -> io::Result<String> {
+    let mut source = String::new();
+    io::stdin().read_to_string(&mut source)?;
 
-        println!("eval: {:?}", ans);
+    Ok(source)
+}
+
+/// Run a whole program, returning the exit status.
+fn run(backend: Backend, name: &str, source: io::Result<String>, argv: Vec<String>) -> i32 {
+    let source = match source {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("rslisp: cannot read {}: {}", name, err);
+            return 2;
+        }
+    };
+
+    let mut interp = Interpreter::with_backend(backend);
+
+    let argv: Vec<Value> = argv
+        .into_iter()
+        .map(|arg| Value::StrLit(arg.into()))
+        .collect();
+    let argv = if argv.is_empty() {
+        Value::Nil
+    } else {
+        Value::ConsList(argv.into_iter().collect())
+    };
+    interp.define_global("*argv*", argv);
+
+    match interp.eval_str(&source) {
+        Ok(_) => 0,
+        Err(Error::Exit(code)) => code,
+        Err(err) => {
+            eprintln!("{}: error: {}", name, err);
+            1
+        }
     }
+}
+
+fn repl(backend: Backend) -> i32 {
+    let mut repl = Repl::with_backend(backend);
 
-    // println!("{:?}", env);
+    let mut editor: Editor<LineHelper, DefaultHistory> = match Editor::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("rslisp: cannot start the line editor: {}", err);
+            return 1;
+        }
+    };
+    editor.set_helper(Some(LineHelper::new(repl.env().clone())));
+
+    let history = history_path();
+
+    if let Some(path) = &history {
+        // There's no history yet on the first run.
+        let _ = editor.load_history(path);
+    }
+
+    let status = loop {
+        let line = match editor.readline(repl.prompt()) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                repl.cancel();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break 0,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                break 1;
+            }
+        };
+
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+
+        let reply = repl.feed(&line);
+
+        // `:reset` replaces the environment, so keep completion in step.
+        if let Some(helper) = editor.helper_mut() {
+            helper.set_scope(repl.env().clone());
+        }
+
+        match reply {
+            Reply::More => {}
+            Reply::Output(output) => {
+                for line in output {
+                    println!("{}", line);
+                }
+            }
+            Reply::Quit(code) => break code,
+        }
+    };
+
+    if let Some(path) = &history {
+        if let Err(err) = editor.save_history(path) {
+            eprintln!("rslisp: cannot save history to {}: {}", path.display(), err);
+        }
+    }
+
+    status
+}
 
-    // let answer = eval(sexprs[0], &mut env);
+/// `~/.rslisp_history`, if there's a home directory.
+fn history_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
 
-    // println!("eval: {:?}", answer)
+    Some(PathBuf::from(home).join(".rslisp_history"))
 }
@@ -1,86 +1,684 @@
+use crate::error::Error;
 use crate::eval::eval;
-use crate::types::{BuiltinFunction, BuiltinMacro, Function, Scope, Type};
+use crate::resolve::{define_value, Resolver};
+use crate::types::{Function, Scope, Type};
 
 use im::Vector;
+use rslisp_derive::builtin;
 
-pub fn define() -> (String, BuiltinMacro) {
-    let fun = |args: Vector<Type>, scope: &mut Scope| -> Type {
-        if let Type::Symbol(sym) = args[0].clone() {
-            scope.put(sym, eval(args[1].clone(), &mut scope.clone()));
+use std::rc::Rc;
 
-            return Type::Nil;
-        } else {
-            let name_and_args = &args[0];
+/// Check that a builtin was called with exactly `expected` arguments.
+pub fn expect_args(name: &str, args: &Vector<Type>, expected: usize) -> Result<(), Error> {
+    if args.len() == expected {
+        Ok(())
+    } else {
+        Err(Error::Arity {
+            name: name.into(),
+            expected,
+            got: args.len(),
+        })
+    }
+}
 
-            let (name, params): (Type, Vector<Type>) = match name_and_args {
-                Type::SExpr(sexpr) => (sexpr[0].clone(), sexpr.skip(1)),
-                _ => panic!("no name for define"),
-            };
+/// Check that a builtin was called with at least `min` arguments, and at most
+/// `max` if it isn't variadic.
+pub fn expect_arg_range(
+    name: &str,
+    args: &Vector<Type>,
+    min: usize,
+    max: Option<usize>,
+) -> Result<(), Error> {
+    let got = args.len();
 
-            let name = name.as_key();
+    match max {
+        Some(max) if min == max => expect_args(name, args, min),
+        Some(max) if got < min || got > max => Err(Error::Message(format!(
+            "{}: expected {} to {} arguments, got {}",
+            name, min, max, got
+        ))),
+        None if got < min => Err(Error::Message(format!(
+            "{}: expected at least {} arguments, got {}",
+            name, min, got
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Define a global, a local, or a function with `(define (name params...) body...)`.
+#[builtin(macro)]
+pub fn define(args: Vector<Type>, scope: &mut Scope) -> Result<Type, Error> {
+    let target = match args.head() {
+        Some(target) => target.clone(),
+        None => return Err(Error::Syntax("define needs a name".into())),
+    };
+    let value = || define_value(&target, &args);
+
+    match target.clone() {
+        Type::Symbol(sym) => {
+            let value = eval(value()?, scope)?;
+            scope.put(sym, value);
+        }
+        Type::Local(depth, index) => {
+            let value = eval(value()?, scope)?;
+            scope.set_local(depth, index, value)?;
+        }
+        Type::Global(slot) => {
+            let value = eval(value()?, scope)?;
+            scope.globals().borrow_mut().set(slot, value);
+        }
+        Type::SExpr(sexpr) => {
+            let name = sexpr[0].as_key()?;
+            let params = sexpr.skip(1);
             let body = args.skip(1);
-            let environ = scope.fork();
-            let func = Type::Function(Function::new(params, body, environ));
 
-            scope.put(name, func);
+            let lambda = Resolver::new(&mut scope.globals().borrow_mut(), scope.frame()).lambda(
+                Some(name),
+                params,
+                body,
+            )?;
+            let func = Function::new(Rc::new(lambda), scope);
+            let func = Type::Function(crate::gc::alloc(func));
 
-            return Type::Nil;
+            scope.put(name, func);
         }
-    };
+        target => {
+            let msg = format!("cannot define {:#}", target);
+            return Err(Error::Syntax(msg));
+        }
+    }
 
-    ("define".into(), BuiltinMacro::new("define".into(), fun))
+    Ok(Type::Nil)
 }
 
 pub mod math {
     use super::*;
 
-    pub fn add() -> (String, BuiltinFunction) {
-        let fun = |args: Vector<Type>, _scope: &mut Scope| -> Type {
-            // println!("add call: {:?}", args);
-
-            let mut sum_int: i64 = 0;
-            let mut sum_float: f64 = 0.0;
+    /// Fold `args` with an integer and a float operation, starting at
+    /// `identity`. The result is a float if any argument is.
+    fn fold(
+        name: &str,
+        args: Vector<Type>,
+        identity: i64,
+        int_op: fn(i64, i64) -> Option<i64>,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Result<Type, Error> {
+        let mut acc_int = identity;
+        let mut acc_float = identity as f64;
+        let mut any_float = false;
 
-            for value in args {
-                match value {
-                    Type::Int(i) => sum_int += i,
-                    Type::Float(f) => sum_float += f,
-                    t => panic!("invalid argument for `add`: {:?}", t),
+        for value in args {
+            match value {
+                Type::Int(i) => {
+                    acc_int = int_op(acc_int, i)
+                        .ok_or_else(|| Error::Message(format!("{}: integer overflow", name)))?;
+                }
+                Type::Float(f) => {
+                    acc_float = float_op(acc_float, f);
+                    any_float = true;
+                }
+                t => {
+                    return Err(Error::WrongType {
+                        name: name.into(),
+                        expected: "a number",
+                        got: t,
+                    })
                 }
             }
+        }
 
-            if sum_float > 0.0 {
-                Type::Float(sum_int as f64 + sum_float)
-            } else {
-                Type::Int(sum_int)
-            }
+        if any_float {
+            Ok(Type::Float(float_op(acc_int as f64, acc_float)))
+        } else {
+            Ok(Type::Int(acc_int))
+        }
+    }
+
+    /// Add numbers.
+    #[builtin(fn, name = "+", alias = "add")]
+    pub fn add(args: Vector<Type>, _scope: &mut Scope) -> Result<Type, Error> {
+        fold("+", args, 0, i64::checked_add, |a, b| a + b)
+    }
+
+    /// Multiply numbers.
+    #[builtin(fn, name = "*", alias = "mul")]
+    pub fn mul(args: Vector<Type>, _scope: &mut Scope) -> Result<Type, Error> {
+        fold("*", args, 1, i64::checked_mul, |a, b| a * b)
+    }
+}
+
+pub mod gc {
+    use super::*;
+
+    use crate::symbol::Symbol;
+
+    /// Run the garbage collector, returning how many objects it freed.
+    #[builtin(fn)]
+    pub fn gc() -> usize {
+        crate::gc::collect()
+    }
+
+    /// The collector's statistics, as an association list.
+    #[builtin(fn, name = "gc-stats")]
+    pub fn gc_stats() -> Type {
+        let stats = crate::gc::stats();
+
+        let entry = |name: &str, value: usize| -> Type {
+            let pair = vec![Type::Symbol(Symbol::intern(name)), Type::Int(value as i64)];
+            Type::ConsList(pair.into_iter().collect())
         };
 
-        ("add".into(), BuiltinFunction::new("add".into(), fun))
+        let entries = vec![
+            entry("live", stats.live),
+            entry("bytes", stats.bytes),
+            entry("collections", stats.collections),
+            entry("freed", stats.freed),
+        ];
+
+        Type::ConsList(entries.into_iter().collect())
     }
+}
 
-    pub fn mul() -> (String, BuiltinFunction) {
-        let fun = |args: Vector<Type>, _scope: &mut Scope| -> Type {
-            // println!("mul call: {:?}", args);
+pub mod io {
+    use super::*;
 
-            let mut mul_int: i64 = 1;
-            let mut mul_float: f64 = 1.0;
+    use crate::convert::Rest;
+    use crate::format;
+    use crate::printer::{self, output};
 
-            for value in args {
-                match value {
-                    Type::Int(i) => mul_int *= i,
-                    Type::Float(f) => mul_float *= f,
-                    t => panic!("invalid argument for `mul`: {:?}", t),
-                }
-            }
+    /// Display the arguments separated by spaces, then a newline.
+    #[builtin(fn)]
+    pub fn print(args: Vector<Type>, _scope: &mut Scope) -> Result<Type, Error> {
+        let text: Vec<String> = args.iter().map(printer::display).collect();
+        output(&text.join(" "))?;
+        output("\n")?;
 
-            if mul_float > 1.0 {
-                Type::Float(mul_int as f64 * mul_float)
-            } else {
-                Type::Int(mul_int)
-            }
+        Ok(Type::Nil)
+    }
+
+    /// Write a value for people to read: strings without quotes.
+    #[builtin(fn)]
+    pub fn display(args: Vector<Type>, _scope: &mut Scope) -> Result<Type, Error> {
+        expect_args("display", &args, 1)?;
+        output(&printer::display(&args[0]))?;
+
+        Ok(Type::Nil)
+    }
+
+    /// Write a value the way the reader reads it back.
+    #[builtin(fn)]
+    pub fn write(args: Vector<Type>, _scope: &mut Scope) -> Result<Type, Error> {
+        expect_args("write", &args, 1)?;
+        output(&printer::write(&args[0]))?;
+
+        Ok(Type::Nil)
+    }
+
+    /// Write a newline.
+    #[builtin(fn)]
+    pub fn newline() -> Result<(), Error> {
+        output("\n")
+    }
+
+    /// Write a control string filled in with the arguments, as by `format`.
+    #[builtin(fn)]
+    pub fn printf(control: Rc<str>, args: Rest<Type>) -> Result<(), Error> {
+        output(&format::format("printf", &control, &args)?)
+    }
+}
+
+pub mod system {
+    use super::*;
+
+    /// Exit with a status from 0 to 255, 0 by default.
+    #[builtin(fn)]
+    pub fn exit(code: Option<u8>) -> Result<Type, Error> {
+        Err(Error::Exit(code.unwrap_or(0).into()))
+    }
+
+    /// The docstring of a builtin, or () if it has none.
+    #[builtin(fn)]
+    pub fn doc(args: Vector<Type>, _scope: &mut Scope) -> Result<Type, Error> {
+        expect_args("doc", &args, 1)?;
+
+        let doc = match &args[0] {
+            Type::Builtin(builtin) => builtin.doc(),
+            Type::Macro(mac) => mac.doc(),
+            _ => None,
         };
 
-        ("mul".into(), BuiltinFunction::new("mul".into(), fun))
+        Ok(doc.map_or(Type::Nil, |doc| Type::StrLit(doc.into())))
+    }
+}
+
+pub mod load {
+    use super::*;
+
+    use std::path::Path;
+
+    /// Evaluate the file at a path, returning its last value.
+    #[builtin(fn)]
+    pub fn load(args: Vector<Type>, scope: &mut Scope) -> Result<Type, Error> {
+        expect_args("load", &args, 1)?;
+
+        match &args[0] {
+            Type::StrLit(path) => crate::load::load(Path::new(&**path), scope),
+            t => Err(Error::WrongType {
+                name: "load".into(),
+                expected: "a path string",
+                got: t.clone(),
+            }),
+        }
+    }
+
+    /// Load a module from the load path, once.
+    #[builtin(fn)]
+    pub fn require(args: Vector<Type>, scope: &mut Scope) -> Result<Type, Error> {
+        expect_args("require", &args, 1)?;
+
+        match &args[0] {
+            Type::Symbol(module) => {
+                crate::load::require(*module, scope)?;
+                Ok(Type::Nil)
+            }
+            t => Err(Error::WrongType {
+                name: "require".into(),
+                expected: "a module name",
+                got: t.clone(),
+            }),
+        }
+    }
+}
+
+pub mod module {
+    use super::*;
+
+    /// Define a module: `(module name (export names...) body...)`.
+    #[builtin(macro)]
+    pub fn module(args: Vector<Type>, scope: &mut Scope) -> Result<Type, Error> {
+        crate::module::define(args, scope)?;
+
+        Ok(Type::Nil)
+    }
+
+    /// Bind the exports of modules: `(import spec...)`.
+    #[builtin(macro)]
+    pub fn import(args: Vector<Type>, scope: &mut Scope) -> Result<Type, Error> {
+        for spec in args.iter() {
+            crate::module::import(spec, scope)?;
+        }
+
+        Ok(Type::Nil)
+    }
+}
+
+pub mod record {
+    use super::*;
+
+    /// Define a record type: `(define-record-type <name> (make-name field...) name? (field accessor [modifier])...)`.
+    #[builtin(macro, name = "define-record-type")]
+    pub fn define_record_type(args: Vector<Type>, scope: &mut Scope) -> Result<Type, Error> {
+        crate::record::define(args, scope)?;
+
+        Ok(Type::Nil)
+    }
+}
+
+pub mod compare {
+    use super::*;
+
+    /// Whether two values are structurally equal: lists by element, records by field.
+    #[builtin(fn, name = "equal?")]
+    pub fn equal(args: Vector<Type>, _scope: &mut Scope) -> Result<Type, Error> {
+        expect_args("equal?", &args, 2)?;
+
+        Ok(Type::Bool(args[0] == args[1]))
+    }
+}
+
+pub mod hash {
+    use super::*;
+
+    use crate::hash::HashTable;
+
+    /// Fail unless `table` can be modified.
+    fn mutable(name: &str, table: &HashTable) -> Result<(), Error> {
+        if table.is_literal() {
+            Err(Error::Message(format!(
+                "{}: can't modify a literal hash table",
+                name
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// A new, empty hash table.
+    #[builtin(fn, name = "make-hash-table")]
+    pub fn make_hash_table() -> Type {
+        Type::HashMap(HashTable::new(vec![]))
+    }
+
+    /// The value for a key, or the default if there's none.
+    #[builtin(fn, name = "hash-ref")]
+    pub fn hash_ref(table: Rc<HashTable>, key: Type, default: Option<Type>) -> Result<Type, Error> {
+        match (table.get(&key), default) {
+            (Some(value), _) | (None, Some(value)) => Ok(value),
+            (None, None) => Err(Error::Message(format!(
+                "hash-ref: no value for the key {:#}",
+                key
+            ))),
+        }
+    }
+
+    /// Set the value for a key.
+    #[builtin(fn, name = "hash-set!")]
+    pub fn hash_set(table: Rc<HashTable>, key: Type, value: Type) -> Result<(), Error> {
+        mutable("hash-set!", &table)?;
+        table.insert(key, value);
+
+        Ok(())
+    }
+
+    /// Remove a key and its value, if it's there.
+    #[builtin(fn, name = "hash-remove!")]
+    pub fn hash_remove(table: Rc<HashTable>, key: Type) -> Result<(), Error> {
+        mutable("hash-remove!", &table)?;
+        table.remove(&key);
+
+        Ok(())
+    }
+
+    /// The keys, in no particular order.
+    #[builtin(fn, name = "hash-keys")]
+    pub fn hash_keys(table: Rc<HashTable>) -> Vec<Type> {
+        table.entries().into_iter().map(|(key, _)| key).collect()
+    }
+
+    /// The values, in no particular order.
+    #[builtin(fn, name = "hash-values")]
+    pub fn hash_values(table: Rc<HashTable>) -> Vec<Type> {
+        table
+            .entries()
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    /// The entries as an association list of `(key . value)` pairs.
+    #[builtin(fn, name = "hash->alist")]
+    pub fn hash_to_alist(table: Rc<HashTable>) -> Vec<Type> {
+        table
+            .entries()
+            .into_iter()
+            .map(|(key, value)| Type::Cons(vec![key, value].into_iter().collect()))
+            .collect()
+    }
+
+    /// The number of entries.
+    #[builtin(fn, name = "hash-count")]
+    pub fn hash_count(table: Rc<HashTable>) -> usize {
+        table.len()
+    }
+}
+
+pub mod vector {
+    use super::*;
+
+    use crate::convert::Rest;
+    use crate::eval::apply;
+    use crate::vector::VectorCell;
+
+    /// Fail unless `vector` can be modified.
+    fn mutable(name: &str, vector: &VectorCell) -> Result<(), Error> {
+        if vector.is_literal() {
+            Err(Error::Message(format!(
+                "{}: can't modify a literal vector",
+                name
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn out_of_range(name: &str, index: usize, vector: &VectorCell) -> Error {
+        Error::Message(format!(
+            "{}: index {} out of range for a vector of length {}",
+            name,
+            index,
+            vector.len()
+        ))
+    }
+
+    /// A vector of the arguments.
+    #[builtin(fn)]
+    pub fn vector(elems: Rest<Type>) -> Type {
+        Type::Vector(VectorCell::new(elems.into_iter().collect()))
+    }
+
+    /// A vector of `k` elements, each the fill value or `()`.
+    #[builtin(fn, name = "make-vector")]
+    pub fn make_vector(k: usize, fill: Option<Type>) -> Type {
+        let fill = fill.unwrap_or(Type::Nil);
+        Type::Vector(VectorCell::new(Vector::from(vec![fill; k])))
+    }
+
+    /// The element at an index.
+    #[builtin(fn, name = "vector-ref")]
+    pub fn vector_ref(vector: Rc<VectorCell>, k: usize) -> Result<Type, Error> {
+        vector
+            .get(k)
+            .ok_or_else(|| out_of_range("vector-ref", k, &vector))
+    }
+
+    /// Replace the element at an index.
+    #[builtin(fn, name = "vector-set!")]
+    pub fn vector_set(vector: Rc<VectorCell>, k: usize, value: Type) -> Result<(), Error> {
+        mutable("vector-set!", &vector)?;
+
+        if vector.set(k, value) {
+            Ok(())
+        } else {
+            Err(out_of_range("vector-set!", k, &vector))
+        }
+    }
+
+    /// The number of elements.
+    #[builtin(fn, name = "vector-length")]
+    pub fn vector_length(vector: Rc<VectorCell>) -> usize {
+        vector.len()
+    }
+
+    /// A list of the elements.
+    #[builtin(fn, name = "vector->list")]
+    pub fn vector_to_list(vector: Rc<VectorCell>) -> Type {
+        match vector.elems() {
+            elems if elems.is_empty() => Type::Nil,
+            elems => Type::ConsList(elems),
+        }
+    }
+
+    /// A vector of a list's elements.
+    #[builtin(fn, name = "list->vector")]
+    pub fn list_to_vector(list: Vec<Type>) -> Type {
+        Type::Vector(VectorCell::new(list.into_iter().collect()))
+    }
+
+    /// Apply a procedure element-wise, stopping at the end of the shortest vector.
+    #[builtin(fn, name = "vector-map")]
+    pub fn vector_map(
+        fun: Type,
+        first: Rc<VectorCell>,
+        scope: &mut Scope,
+        rest: Rest<Rc<VectorCell>>,
+    ) -> Result<Type, Error> {
+        let vectors: Vec<Vector<Type>> = std::iter::once(first)
+            .chain(rest)
+            .map(|vector| vector.elems())
+            .collect();
+        let len = vectors.iter().map(Vector::len).min().unwrap_or(0);
+
+        let elems = (0..len)
+            .map(|i| {
+                let args = vectors.iter().map(|elems| elems[i].clone()).collect();
+                apply(fun.clone(), args, scope)
+            })
+            .collect::<Result<Vector<Type>, Error>>()?;
+
+        Ok(Type::Vector(VectorCell::new(elems)))
+    }
+
+    /// Set every element to a value.
+    #[builtin(fn, name = "vector-fill!")]
+    pub fn vector_fill(vector: Rc<VectorCell>, fill: Type) -> Result<(), Error> {
+        mutable("vector-fill!", &vector)?;
+        vector.fill(fill);
+
+        Ok(())
+    }
+}
+
+pub mod string {
+    use super::*;
+
+    use crate::convert::Rest;
+    use crate::format;
+    use crate::printer;
+    use crate::string::{char_len, char_slice, parse_number};
+    use crate::symbol::Symbol;
+
+    /// The number of characters in a string.
+    #[builtin(fn, name = "string-length")]
+    pub fn string_length(string: Rc<str>) -> usize {
+        char_len(&string)
+    }
+
+    /// The strings joined end to end.
+    #[builtin(fn, name = "string-append")]
+    pub fn string_append(strings: Rest<Rc<str>>) -> String {
+        strings.concat()
+    }
+
+    /// The characters from a start index up to an end index, or to the end.
+    #[builtin(fn)]
+    pub fn substring(string: Rc<str>, start: usize, end: Option<usize>) -> Result<String, Error> {
+        let len = char_len(&string);
+        let end = end.unwrap_or(len);
+
+        match char_slice(&string, start, end) {
+            Some(slice) => Ok(slice.into()),
+            None => Err(Error::Message(format!(
+                "substring: range {} to {} out of range for a string of length {}",
+                start, end, len
+            ))),
+        }
+    }
+
+    /// The character at an index, as a string of length one.
+    #[builtin(fn, name = "string-ref")]
+    pub fn string_ref(string: Rc<str>, k: usize) -> Result<String, Error> {
+        match string.chars().nth(k) {
+            Some(ch) => Ok(ch.to_string()),
+            None => Err(Error::Message(format!(
+                "string-ref: index {} out of range for a string of length {}",
+                k,
+                char_len(&string)
+            ))),
+        }
+    }
+
+    /// A string in upper case.
+    #[builtin(fn, name = "string-upcase")]
+    pub fn string_upcase(string: Rc<str>) -> String {
+        string.to_uppercase()
+    }
+
+    /// A string in lower case.
+    #[builtin(fn, name = "string-downcase")]
+    pub fn string_downcase(string: Rc<str>) -> String {
+        string.to_lowercase()
+    }
+
+    /// The pieces of a string between separators, or between runs of
+    /// whitespace if no separator is given.
+    #[builtin(fn, name = "string-split")]
+    pub fn string_split(string: Rc<str>, separator: Option<Rc<str>>) -> Vec<String> {
+        match separator {
+            Some(separator) => string.split(&*separator).map(String::from).collect(),
+            None => string.split_whitespace().map(String::from).collect(),
+        }
+    }
+
+    /// A list of strings joined with a separator, a space by default.
+    #[builtin(fn, name = "string-join")]
+    pub fn string_join(strings: Vec<Rc<str>>, separator: Option<Rc<str>>) -> String {
+        strings.join(separator.as_deref().unwrap_or(" "))
+    }
+
+    /// A string without leading and trailing whitespace.
+    #[builtin(fn, name = "string-trim")]
+    pub fn string_trim(string: Rc<str>) -> String {
+        string.trim().into()
+    }
+
+    /// The index where a string first contains another, or #f if it doesn't.
+    #[builtin(fn, name = "string-contains")]
+    pub fn string_contains(string: Rc<str>, needle: Rc<str>) -> Type {
+        match string.find(&*needle) {
+            Some(offset) => Type::Int(char_len(&string[..offset]) as i64),
+            None => Type::Bool(false),
+        }
+    }
+
+    /// A string with every occurrence of a pattern replaced.
+    #[builtin(fn, name = "string-replace")]
+    pub fn string_replace(string: Rc<str>, from: Rc<str>, to: Rc<str>) -> String {
+        string.replace(&*from, &to)
+    }
+
+    /// The number a string reads as, or #f if it isn't one.
+    #[builtin(fn, name = "string->number")]
+    pub fn string_to_number(string: Rc<str>) -> Type {
+        parse_number(&string).unwrap_or(Type::Bool(false))
+    }
+
+    /// A number as a string, the way it prints.
+    #[builtin(fn, name = "number->string")]
+    pub fn number_to_string(number: Type) -> Result<String, Error> {
+        match number {
+            Type::Int(_) | Type::Float(_) => Ok(printer::display(&number)),
+            got => Err(Error::WrongType {
+                name: "number->string".into(),
+                expected: "a number",
+                got,
+            }),
+        }
+    }
+
+    /// The symbol with a string as its name.
+    #[builtin(fn, name = "string->symbol")]
+    pub fn string_to_symbol(string: Rc<str>) -> Symbol {
+        Symbol::intern(&string)
+    }
+
+    /// The name of a symbol.
+    #[builtin(fn, name = "symbol->string")]
+    pub fn symbol_to_string(symbol: Symbol) -> &'static str {
+        symbol.as_str()
+    }
+
+    /// The characters of a string, each as a string of length one.
+    #[builtin(fn, name = "string->list")]
+    pub fn string_to_list(string: Rc<str>) -> Vec<String> {
+        string.chars().map(String::from).collect()
+    }
+
+    /// A control string filled in with the arguments: `~a` displays the
+    /// next one, `~s` writes it, `~d` and `~x` print a number in decimal and
+    /// hex, `~%` is a newline and `~~` a tilde. Options such as `~-10a` or
+    /// `~08.3d` set a width and precision, as in `printf`.
+    #[builtin(fn)]
+    pub fn format(control: Rc<str>, args: Rest<Type>) -> Result<String, Error> {
+        format::format("format", &control, &args)
     }
 }
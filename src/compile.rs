@@ -0,0 +1,146 @@
+use crate::error::Error;
+use crate::eval::quote;
+use crate::types::{Globals, Lambda, Type};
+
+use im::Vector;
+
+/// A single instruction for the stack machine in `vm`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    /// Push a constant from the chunk.
+    Const(usize),
+    /// Push the variable at `(depth, index)` in the frame chain.
+    Local(usize, usize),
+    /// Push a global by slot.
+    Global(usize),
+    /// Pop a value into `(depth, index)` and push `Nil`.
+    SetLocal(usize, usize),
+    /// Pop a value into a global slot and push `Nil`.
+    SetGlobal(usize),
+    /// Close the `Type::Lambda` constant over the current frame.
+    Closure(usize),
+    /// Call the value below the top `n` arguments with those arguments.
+    Call(usize),
+    /// Hand the constant form to the tree-walking evaluator. Used for macro
+    /// calls, whose arguments must stay unevaluated.
+    Eval(usize),
+    Pop,
+}
+
+/// Compiled code for a function body or a top-level form.
+#[derive(Debug, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub consts: Vec<Type>,
+}
+
+/// Compiles resolved expressions (see `resolve`) into a `Chunk`.
+pub struct Compiler<'g> {
+    globals: &'g Globals,
+    chunk: Chunk,
+}
+
+impl<'g> Compiler<'g> {
+    pub fn new(globals: &'g Globals) -> Compiler<'g> {
+        Compiler {
+            globals,
+            chunk: Chunk::default(),
+        }
+    }
+
+    /// Compile a top-level form.
+    pub fn expr(mut self, expr: Type) -> Result<Chunk, Error> {
+        self.compile(expr)?;
+        Ok(self.chunk)
+    }
+
+    /// Compile a function body, leaving the value of the last form.
+    pub fn lambda(mut self, lambda: &Lambda) -> Result<Chunk, Error> {
+        if lambda.body().is_empty() {
+            self.constant(Type::Nil);
+        }
+
+        for (i, expr) in lambda.body().iter().enumerate() {
+            if i > 0 {
+                self.chunk.code.push(Op::Pop);
+            }
+
+            self.compile(expr.clone())?;
+        }
+
+        Ok(self.chunk)
+    }
+
+    fn compile(&mut self, expr: Type) -> Result<(), Error> {
+        match expr {
+            Type::Local(depth, index) => self.chunk.code.push(Op::Local(depth, index)),
+            Type::Global(slot) => self.chunk.code.push(Op::Global(slot)),
+            Type::Quoted(elem) => self.constant(quote((*elem).clone())),
+            Type::Lambda(_) => {
+                let index = self.add_const(expr);
+                self.chunk.code.push(Op::Closure(index));
+            }
+            Type::SExpr(sexpr) => return self.sexpr(sexpr),
+            Type::Symbol(_) => self.fallback(expr),
+            _ => self.constant(expr),
+        }
+
+        Ok(())
+    }
+
+    fn sexpr(&mut self, sexpr: Vector<Type>) -> Result<(), Error> {
+        match sexpr.head() {
+            Some(Type::Global(slot)) if self.is_define(*slot) => return self.define(sexpr),
+            Some(Type::Symbol(_)) => self.fallback(Type::SExpr(sexpr)),
+            _ => {
+                let argc = sexpr.len() - 1;
+
+                for expr in sexpr {
+                    self.compile(expr)?;
+                }
+
+                self.chunk.code.push(Op::Call(argc));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_define(&self, slot: usize) -> bool {
+        match self.globals.value(slot) {
+            Some(Type::Macro(mac)) => mac.name() == "define",
+            _ => false,
+        }
+    }
+
+    fn define(&mut self, sexpr: Vector<Type>) -> Result<(), Error> {
+        let op = match sexpr.get(1) {
+            Some(Type::Local(depth, index)) => Op::SetLocal(*depth, *index),
+            Some(Type::Global(slot)) => Op::SetGlobal(*slot),
+            target => {
+                let target = target.cloned().unwrap_or(Type::Nil);
+                return Err(Error::Syntax(format!("cannot define {:#}", target)));
+            }
+        };
+
+        self.compile(sexpr.get(2).cloned().unwrap_or(Type::Nil))?;
+        self.chunk.code.push(op);
+
+        Ok(())
+    }
+
+    fn fallback(&mut self, expr: Type) {
+        let index = self.add_const(expr);
+        self.chunk.code.push(Op::Eval(index));
+    }
+
+    fn constant(&mut self, value: Type) {
+        let index = self.add_const(value);
+        self.chunk.code.push(Op::Const(index));
+    }
+
+    fn add_const(&mut self, value: Type) -> usize {
+        self.chunk.consts.push(value);
+        self.chunk.consts.len() - 1
+    }
+}
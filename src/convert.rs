@@ -0,0 +1,516 @@
+//! Conversions between Lisp values and Rust types.
+//!
+//! `FromLisp` takes a value apart, failing with `Error::Conversion` when it's
+//! the wrong kind of value, and `IntoLisp` builds one, failing only for
+//! integers too wide for an `i64`. Lists convert to and
+//! from `Vec`s and tuples, association lists to and from `HashMap`s, and
+//! `()` stands for `None`.
+//!
+//! `IntoBuiltin` builds on both to turn a typed Rust closure into a builtin.
+
+use crate::builtins::expect_args;
+use crate::error::Error;
+use crate::symbol::Symbol;
+use crate::types::{BuiltinFunction, Scope, Type};
+
+use im::Vector;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::Hash;
+use std::rc::Rc;
+
+pub trait FromLisp: Sized {
+    fn from_lisp(value: Type) -> Result<Self, Error>;
+}
+
+pub trait IntoLisp {
+    fn into_lisp(self) -> Result<Type, Error>;
+}
+
+fn mismatch<T>(expected: &'static str, got: Type) -> Result<T, Error> {
+    Err(Error::Conversion { expected, got })
+}
+
+/// The elements of a list, with `()` as the empty list.
+fn elements(value: Type, expected: &'static str) -> Result<Vector<Type>, Error> {
+    match value {
+        Type::ConsList(list) => Ok(list),
+        Type::Nil => Ok(Vector::new()),
+        got => mismatch(expected, got),
+    }
+}
+
+/// A list of `items`, with `()` as the empty list.
+fn list(items: Vector<Type>) -> Type {
+    if items.is_empty() {
+        Type::Nil
+    } else {
+        Type::ConsList(items)
+    }
+}
+
+impl FromLisp for Type {
+    fn from_lisp(value: Type) -> Result<Type, Error> {
+        Ok(value)
+    }
+}
+
+impl IntoLisp for Type {
+    fn into_lisp(self) -> Result<Type, Error> {
+        Ok(self)
+    }
+}
+
+impl IntoLisp for () {
+    fn into_lisp(self) -> Result<Type, Error> {
+        Ok(Type::Nil)
+    }
+}
+
+macro_rules! integer {
+    ($($int:ty),*) => {
+        $(
+            impl FromLisp for $int {
+                fn from_lisp(value: Type) -> Result<$int, Error> {
+                    let expected = concat!("an integer that fits in ", stringify!($int));
+
+                    match value {
+                        Type::Int(int) => match <$int>::try_from(int) {
+                            Ok(int) => Ok(int),
+                            Err(_) => mismatch(expected, value),
+                        },
+                        got => mismatch(expected, got),
+                    }
+                }
+            }
+
+            impl IntoLisp for $int {
+                /// Fails for integers that don't fit in an `i64`.
+                fn into_lisp(self) -> Result<Type, Error> {
+                    match i64::try_from(self) {
+                        Ok(int) => Ok(Type::Int(int)),
+                        Err(_) => Err(Error::Message(format!(
+                            "{} doesn't fit in an integer",
+                            self
+                        ))),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl FromLisp for f64 {
+    fn from_lisp(value: Type) -> Result<f64, Error> {
+        match value {
+            Type::Float(flo) => Ok(flo),
+            Type::Int(int) => Ok(int as f64),
+            got => mismatch("a number", got),
+        }
+    }
+}
+
+impl IntoLisp for f64 {
+    fn into_lisp(self) -> Result<Type, Error> {
+        Ok(Type::Float(self))
+    }
+}
+
+impl FromLisp for f32 {
+    fn from_lisp(value: Type) -> Result<f32, Error> {
+        f64::from_lisp(value).map(|flo| flo as f32)
+    }
+}
+
+impl IntoLisp for f32 {
+    fn into_lisp(self) -> Result<Type, Error> {
+        Ok(Type::Float(self.into()))
+    }
+}
+
+impl FromLisp for bool {
+    fn from_lisp(value: Type) -> Result<bool, Error> {
+        match value {
+            Type::Bool(b) => Ok(b),
+            got => mismatch("a boolean", got),
+        }
+    }
+}
+
+impl IntoLisp for bool {
+    fn into_lisp(self) -> Result<Type, Error> {
+        Ok(Type::Bool(self))
+    }
+}
+
+impl FromLisp for Rc<str> {
+    fn from_lisp(value: Type) -> Result<Rc<str>, Error> {
+        match value {
+            Type::StrLit(string) => Ok(string),
+            got => mismatch("a string", got),
+        }
+    }
+}
+
+impl FromLisp for String {
+    fn from_lisp(value: Type) -> Result<String, Error> {
+        Rc::<str>::from_lisp(value).map(|string| string.to_string())
+    }
+}
+
+impl IntoLisp for String {
+    fn into_lisp(self) -> Result<Type, Error> {
+        Ok(Type::StrLit(self.into()))
+    }
+}
+
+impl IntoLisp for &str {
+    fn into_lisp(self) -> Result<Type, Error> {
+        Ok(Type::StrLit(self.into()))
+    }
+}
+
+impl FromLisp for Symbol {
+    fn from_lisp(value: Type) -> Result<Symbol, Error> {
+        match value {
+            Type::Symbol(sym) => Ok(sym),
+            got => mismatch("a symbol", got),
+        }
+    }
+}
+
+impl IntoLisp for Symbol {
+    fn into_lisp(self) -> Result<Type, Error> {
+        Ok(Type::Symbol(self))
+    }
+}
+
+impl<T: FromLisp> FromLisp for Vec<T> {
+    fn from_lisp(value: Type) -> Result<Vec<T>, Error> {
+        elements(value, "a list")?
+            .into_iter()
+            .map(T::from_lisp)
+            .collect()
+    }
+}
+
+impl<T: IntoLisp> IntoLisp for Vec<T> {
+    fn into_lisp(self) -> Result<Type, Error> {
+        Ok(list(
+            self.into_iter()
+                .map(T::into_lisp)
+                .collect::<Result<_, _>>()?,
+        ))
+    }
+}
+
+impl<T: FromLisp> FromLisp for Option<T> {
+    fn from_lisp(value: Type) -> Result<Option<T>, Error> {
+        match value {
+            Type::Nil => Ok(None),
+            value => T::from_lisp(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoLisp> IntoLisp for Option<T> {
+    fn into_lisp(self) -> Result<Type, Error> {
+        match self {
+            Some(value) => value.into_lisp(),
+            None => Ok(Type::Nil),
+        }
+    }
+}
+
+/// Maps convert from association lists, whose entries are pairs `(k . v)`
+/// or two element lists `(k v)`.
+impl<K: FromLisp + Eq + Hash, V: FromLisp> FromLisp for HashMap<K, V> {
+    fn from_lisp(value: Type) -> Result<HashMap<K, V>, Error> {
+        let expected = "an association list";
+
+        elements(value, expected)?
+            .into_iter()
+            .map(|entry| match entry {
+                Type::Cons(pair) | Type::ConsList(pair) if pair.len() == 2 => {
+                    let (key, value) = (pair[0].clone(), pair[1].clone());
+                    Ok((K::from_lisp(key)?, V::from_lisp(value)?))
+                }
+                got => mismatch(expected, got),
+            })
+            .collect()
+    }
+}
+
+/// Maps convert to association lists of pairs.
+impl<K: IntoLisp, V: IntoLisp> IntoLisp for HashMap<K, V> {
+    fn into_lisp(self) -> Result<Type, Error> {
+        let pairs = self
+            .into_iter()
+            .map(|(key, value)| {
+                let pair = vec![key.into_lisp()?, value.into_lisp()?];
+                Ok(Type::Cons(pair.into_iter().collect()))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(list(pairs))
+    }
+}
+
+macro_rules! tuple {
+    ($len:expr, $expected:expr, $($name:ident),*) => {
+        /// Tuples convert from lists of exactly their length.
+        impl<$($name: FromLisp),*> FromLisp for ($($name,)*) {
+            fn from_lisp(value: Type) -> Result<($($name,)*), Error> {
+                let items = match value {
+                    Type::ConsList(items) if items.len() == $len => items,
+                    got => return mismatch($expected, got),
+                };
+                let mut items = items.into_iter();
+
+                Ok(($($name::from_lisp(items.next().unwrap())?,)*))
+            }
+        }
+
+        impl<$($name: IntoLisp),*> IntoLisp for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn into_lisp(self) -> Result<Type, Error> {
+                let ($($name,)*) = self;
+                Ok(Type::ConsList(vec![$($name.into_lisp()?),*].into_iter().collect()))
+            }
+        }
+    };
+}
+
+tuple!(1, "a list of 1 element", A);
+tuple!(2, "a list of 2 elements", A, B);
+tuple!(3, "a list of 3 elements", A, B, C);
+tuple!(4, "a list of 4 elements", A, B, C, D);
+tuple!(5, "a list of 5 elements", A, B, C, D, E);
+tuple!(6, "a list of 6 elements", A, B, C, D, E, F);
+
+/// What a registered closure can return: a value, or a `Result` for
+/// closures that can fail.
+pub trait IntoLispResult {
+    /// The result of the builtin `name`, naming it if the value doesn't
+    /// convert.
+    fn into_lisp_result(self, name: &str) -> Result<Type, Error>;
+}
+
+impl<T: IntoLisp> IntoLispResult for T {
+    fn into_lisp_result(self, name: &str) -> Result<Type, Error> {
+        self.into_lisp()
+            .map_err(|err| Error::Message(format!("{}: {}", name, err)))
+    }
+}
+
+impl<T: IntoLisp> IntoLispResult for Result<T, Error> {
+    fn into_lisp_result(self, name: &str) -> Result<Type, Error> {
+        self?.into_lisp_result(name)
+    }
+}
+
+/// The trailing arguments of a variadic builtin, each converted to `T`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rest<T>(pub Vec<T>);
+
+impl<T> Rest<T> {
+    pub fn into_vec(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> std::ops::Deref for Rest<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+}
+
+impl<T> IntoIterator for Rest<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Convert an argument to the builtin `name`, naming it in the error.
+pub fn argument<T: FromLisp>(name: &str, value: Type) -> Result<T, Error> {
+    T::from_lisp(value).map_err(|err| match err {
+        Error::Conversion { expected, got } => Error::WrongType {
+            name: name.into(),
+            expected,
+            got,
+        },
+        err => err,
+    })
+}
+
+/// A Rust closure that can become a builtin, with its arguments converted
+/// by `FromLisp` and its result by `IntoLisp`. `Args` is the tuple of its
+/// argument types.
+pub trait IntoBuiltin<Args> {
+    fn into_builtin(self, name: &str) -> BuiltinFunction;
+}
+
+macro_rules! into_builtin {
+    ($($arg:ident),*) => {
+        impl<Fun, Ret, $($arg),*> IntoBuiltin<($($arg,)*)> for Fun
+        where
+            Fun: Fn($($arg),*) -> Ret + 'static,
+            Ret: IntoLispResult,
+            $($arg: FromLisp,)*
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_builtin(self, name: &str) -> BuiltinFunction {
+                let owned = name.to_string();
+                let params: &[&str] = &[$(stringify!($arg)),*];
+                let arity = params.len();
+
+                let fun = move |args: Vector<Type>, _scope: &mut Scope| -> Result<Type, Error> {
+                    expect_args(&owned, &args, arity)?;
+
+                    let mut args = args.into_iter();
+                    $(let $arg = argument(&owned, args.next().unwrap())?;)*
+
+                    self($($arg),*).into_lisp_result(&owned)
+                };
+
+                BuiltinFunction::new(name.into(), fun)
+            }
+        }
+    };
+}
+
+into_builtin!();
+into_builtin!(A);
+into_builtin!(A, B);
+into_builtin!(A, B, C);
+into_builtin!(A, B, C, D);
+into_builtin!(A, B, C, D, E);
+into_builtin!(A, B, C, D, E, F);
+into_builtin!(A, B, C, D, E, F, G);
+into_builtin!(A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::parser;
+
+    fn read(code: &str) -> Type {
+        crate::eval::quote(parser::read(code).unwrap().remove(0))
+    }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(i32::from_lisp(Type::Int(-4)), Ok(-4));
+        assert_eq!(f64::from_lisp(Type::Int(2)), Ok(2.0));
+        assert_eq!(bool::from_lisp(read("#t")), Ok(true));
+        assert_eq!(String::from_lisp(read("\"hi\"")), Ok("hi".to_string()));
+        assert_eq!("hi".into_lisp(), Ok(Type::StrLit("hi".into())));
+        assert_eq!(2.5.into_lisp(), Ok(Type::Float(2.5)));
+    }
+
+    #[test]
+    fn typed_errors() {
+        assert_eq!(
+            u8::from_lisp(Type::Int(300)),
+            Err(Error::Conversion {
+                expected: "an integer that fits in u8",
+                got: Type::Int(300)
+            })
+        );
+        assert_eq!(
+            i64::from_lisp(Type::Float(1.5)).unwrap_err().to_string(),
+            "expected an integer that fits in i64, got 1.5"
+        );
+        assert!(Vec::<i64>::from_lisp(read("(1 \"two\")")).is_err());
+        assert_eq!(
+            u64::MAX.into_lisp().unwrap_err().to_string(),
+            "18446744073709551615 doesn't fit in an integer"
+        );
+        assert_eq!(
+            vec![0, u64::MAX].into_lisp().map_err(|err| err.to_string()),
+            Err("18446744073709551615 doesn't fit in an integer".to_string())
+        );
+        assert_eq!((i64::MAX as u64).into_lisp(), Ok(Type::Int(i64::MAX)));
+    }
+
+    #[test]
+    fn collections() {
+        assert_eq!(Vec::<i64>::from_lisp(read("(1 2 3)")), Ok(vec![1, 2, 3]));
+        assert_eq!(Vec::<i64>::from_lisp(Type::Nil), Ok(vec![]));
+        assert_eq!(vec![1, 2].into_lisp(), Ok(read("(1 2)")));
+
+        assert_eq!(Option::<i64>::from_lisp(Type::Nil), Ok(None));
+        assert_eq!(Option::<i64>::from_lisp(Type::Int(1)), Ok(Some(1)));
+        assert_eq!(None::<i64>.into_lisp(), Ok(Type::Nil));
+
+        assert_eq!(
+            <(i64, String, bool)>::from_lisp(read("(1 \"a\" #f)")),
+            Ok((1, "a".to_string(), false))
+        );
+        assert!(<(i64, i64)>::from_lisp(read("(1 2 3)")).is_err());
+        assert_eq!((1, "a").into_lisp(), Ok(read("(1 \"a\")")));
+    }
+
+    #[test]
+    fn typed_builtin() {
+        use crate::types::BuiltinCall;
+
+        let clamp = (|x: f64, lo: f64, hi: f64| x.max(lo).min(hi)).into_builtin("clamp");
+        let mut env = crate::eval::create_env();
+        let args = |code: &str| match read(code) {
+            Type::ConsList(args) => args,
+            _ => unreachable!(),
+        };
+
+        assert_eq!(
+            clamp.call_builtin(args("(5 0 2.5)"), &mut env),
+            Ok(Type::Float(2.5))
+        );
+        assert_eq!(
+            clamp.call_builtin(args("(5 0)"), &mut env),
+            Err(Error::Arity {
+                name: "clamp".into(),
+                expected: 3,
+                got: 2
+            })
+        );
+        assert_eq!(
+            clamp.call_builtin(args("(5 \"lo\" 1)"), &mut env),
+            Err(Error::WrongType {
+                name: "clamp".into(),
+                expected: "a number",
+                got: Type::StrLit("lo".into())
+            })
+        );
+
+        let twice = (|x: u64| x * 2).into_builtin("twice");
+
+        assert_eq!(twice.call_builtin(args("(3)"), &mut env), Ok(Type::Int(6)));
+        assert_eq!(
+            twice.call_builtin(args("(9223372036854775807)"), &mut env),
+            Err(Error::Message(
+                "twice: 18446744073709551614 doesn't fit in an integer".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn maps() {
+        let map = HashMap::<Symbol, i64>::from_lisp(read("((a 1) (b 2))")).unwrap();
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map[&Symbol::intern("b")], 2);
+
+        let back = HashMap::<Symbol, i64>::from_lisp(map.clone().into_lisp().unwrap());
+        assert_eq!(back, Ok(map));
+    }
+}
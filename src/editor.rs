@@ -0,0 +1,189 @@
+//! Line editing support for the REPL: tab completion of bound names and
+//! highlighting of the paren matching the one before the cursor.
+
+use crate::lexer::Lexer;
+use crate::types::Scope;
+
+use rustyline::completion::Completer;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use std::borrow::Cow;
+use std::cell::Cell;
+
+/// Forms the resolver recognises by name, whatever they're bound to.
+pub const SPECIAL_FORMS: &[&str] = &["define"];
+
+/// The REPL's meta-commands.
+pub const COMMANDS: &[&str] = &[":env", ":quit", ":reset"];
+
+pub struct LineHelper {
+    scope: Scope,
+    /// The open paren to highlight on the next refresh.
+    paren: Cell<Option<usize>>,
+}
+
+impl LineHelper {
+    pub fn new(scope: Scope) -> LineHelper {
+        LineHelper {
+            scope,
+            paren: Cell::new(None),
+        }
+    }
+
+    /// Complete from the names bound in `scope` from now on.
+    pub fn set_scope(&mut self, scope: Scope) {
+        self.scope = scope;
+    }
+
+    /// Where the word ending at `pos` starts, and the candidates for it.
+    pub fn completions(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !Lexer::allowed_in_symbol(c))
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        let prefix = &line[start..pos];
+
+        let mut names: Vec<String> = if line[..start].trim().is_empty() && prefix.starts_with(':') {
+            COMMANDS.iter().map(|command| command.to_string()).collect()
+        } else {
+            let bound = self.scope.names().into_iter().map(|name| name.to_string());
+            let forms = SPECIAL_FORMS.iter().map(|form| form.to_string());
+
+            bound.chain(forms).collect()
+        };
+
+        names.retain(|name| name.starts_with(prefix));
+        names.sort();
+        names.dedup();
+
+        (start, names)
+    }
+}
+
+/// The index of the paren that opens the list closed at `close`, skipping
+/// strings and comments.
+pub fn matching_paren(line: &str, close: usize) -> Option<usize> {
+    let mut open = Vec::new();
+    let mut chars = line.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '(' => open.push(i),
+            ')' if i == close => return open.pop(),
+            ')' => {
+                open.pop();
+            }
+            '"' | '|' => {
+                while let Some((_, inner)) = chars.next() {
+                    if inner == '\\' {
+                        chars.next();
+                    } else if inner == c {
+                        break;
+                    }
+                }
+            }
+            ';' => {
+                for (_, inner) in chars.by_ref() {
+                    if inner == '\n' {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+impl Completer for LineHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.completions(line, pos))
+    }
+}
+
+impl Highlighter for LineHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        match self.paren.get() {
+            Some(open) => {
+                let mut line = line.to_owned();
+                line.replace_range(open..=open, "\x1b[1;34m(\x1b[0m");
+
+                Cow::Owned(line)
+            }
+            None => Cow::Borrowed(line),
+        }
+    }
+
+    fn highlight_char(&self, line: &str, pos: usize, kind: CmdKind) -> bool {
+        let open = match kind {
+            CmdKind::ForcedRefresh => None,
+            _ if line[..pos].ends_with(')') => matching_paren(line, pos - 1),
+            _ => None,
+        };
+
+        // Refresh when a highlight appears or goes away.
+        let changed = open.is_some() || self.paren.get().is_some();
+        self.paren.set(open);
+
+        changed
+    }
+}
+
+impl Hinter for LineHelper {
+    type Hint = String;
+}
+
+impl Validator for LineHelper {}
+
+impl Helper for LineHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::eval::{create_env, eval_str};
+
+    #[test]
+    fn complete_bound_names() {
+        let mut env = create_env();
+        eval_str("(define display-all 1)", &mut env).unwrap();
+
+        let helper = LineHelper::new(env);
+
+        assert_eq!(
+            helper.completions("(disp", 5),
+            (1, vec!["display".to_string(), "display-all".to_string()])
+        );
+        assert_eq!(
+            helper.completions("(+ 1 (de", 8),
+            (
+                6,
+                vec!["define".to_string(), "define-record-type".to_string()]
+            )
+        );
+        assert_eq!(helper.completions(":r", 2), (0, vec![":reset".to_string()]));
+    }
+
+    #[test]
+    fn match_parens() {
+        let line = r#"(a (b ")") ; )
+            c)"#;
+
+        assert_eq!(matching_paren(line, 9), Some(3));
+        assert_eq!(matching_paren(line, line.len() - 1), Some(0));
+        assert_eq!(matching_paren("a)", 1), None);
+    }
+}
@@ -0,0 +1,64 @@
+use crate::symbol::Symbol;
+use crate::types::Type;
+
+use std::fmt;
+
+/// Everything that can go wrong reading or evaluating code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The input ended in the middle of an expression.
+    Incomplete,
+    /// The input isn't valid syntax.
+    Syntax(String),
+    /// A variable was used before it was defined.
+    Unbound(Symbol),
+    /// A value that isn't a procedure was called.
+    NotCallable(Type),
+    /// A procedure was called with the wrong number of arguments.
+    Arity {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+    /// A procedure was passed a value of the wrong type.
+    WrongType {
+        name: String,
+        expected: &'static str,
+        got: Type,
+    },
+    /// A value couldn't be converted to a Rust type; see `convert`.
+    Conversion { expected: &'static str, got: Type },
+    /// Any other failure, described by its message.
+    Message(String),
+    /// `(exit n)` was called. Not a failure as such, but it unwinds the
+    /// same way so the host can end the program with status `n`.
+    Exit(i32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Incomplete => f.write_str("unexpected end of input"),
+            Error::Syntax(msg) => write!(f, "syntax error: {}", msg),
+            Error::Unbound(sym) => write!(f, "unbound variable: {}", sym),
+            Error::NotCallable(value) => write!(f, "not a procedure: {:#}", value),
+            Error::Arity {
+                name,
+                expected,
+                got,
+            } => write!(f, "{}: expected {} arguments, got {}", name, expected, got),
+            Error::WrongType {
+                name,
+                expected,
+                got,
+            } => write!(f, "{}: expected {}, got {:#}", name, expected, got),
+            Error::Conversion { expected, got } => {
+                write!(f, "expected {}, got {:#}", expected, got)
+            }
+            Error::Message(msg) => f.write_str(msg),
+            Error::Exit(code) => write!(f, "exit with status {}", code),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
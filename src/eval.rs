@@ -1,62 +1,115 @@
-use crate::types::{BuiltinCall, Call, Scope, Type};
+use crate::error::Error;
+use crate::gc;
+use crate::lexer::Lexer;
+use crate::parser::parse;
+use crate::resolve::resolve_in;
+use crate::symbol::Symbol;
+use crate::types::{BuiltinCall, Call, Function, Scope, Type};
 
 use im::Vector;
 
-use std::cell::RefCell;
 use std::collections::HashMap;
 use std::iter::FromIterator;
-use std::rc::Rc;
 
-// macro_rules! generate_env {
-//     () => {
+/// A global environment with every registered builtin, and `*load-path*`.
+pub fn create_env() -> Scope {
+    let mut funcs: HashMap<Symbol, Type> = crate::registry::bindings().collect();
 
-//     };
-// }
+    funcs.insert(
+        crate::load::LOAD_PATH.into(),
+        crate::load::default_load_path(),
+    );
 
-pub fn create_env() -> Scope {
-    let mut funcs: HashMap<String, Type> = HashMap::new();
+    Scope::new(funcs)
+}
 
-    let (add, add_fn) = crate::builtins::math::add();
-    let (mul, mul_fn) = crate::builtins::math::mul();
-    let (define, define_fn) = crate::builtins::define();
+/// Evaluate every expression in `source`, returning the value of the last
+/// one, or the first error.
+pub fn eval_str(source: &str, stg: &mut Scope) -> Result<Type, Error> {
+    let mut lexer = Lexer::new(source);
+    let mut value = Type::Nil;
 
-    let add_fn = Type::Builtin(Rc::new(RefCell::new(add_fn)));
-    let mul_fn = Type::Builtin(Rc::new(RefCell::new(mul_fn)));
-    let define_fn = Type::Macro(Rc::new(RefCell::new(define_fn)));
+    for expr in parse(&mut lexer) {
+        value = eval_top_level(expr?, stg)?;
+    }
 
-    funcs.insert(define, define_fn);
-    funcs.insert(add, add_fn.clone());
-    funcs.insert(mul, mul_fn.clone());
-    funcs.insert("+".into(), add_fn);
-    funcs.insert("*".into(), mul_fn);
+    Ok(value)
+}
 
-    Scope::new(funcs)
+/// Evaluating code in the tests of every module.
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+
+    /// Evaluate `code` in a fresh environment.
+    pub fn run(code: &str) -> Result<Type, Error> {
+        run_after("", code)
+    }
+
+    /// Evaluate `prelude` and then `code` in a fresh environment.
+    pub fn run_after(prelude: &str, code: &str) -> Result<Type, Error> {
+        let mut env = create_env();
+        eval_str(prelude, &mut env)?;
+        eval_str(code, &mut env)
+    }
+
+    /// `run`, with the value in `write` form and the error as its message.
+    pub fn written(code: &str) -> Result<String, String> {
+        run(code)
+            .map(|value| format!("{:#}", value))
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Call `callee` with already evaluated `args`.
+pub fn apply(callee: Type, args: Vector<Type>, stg: &mut Scope) -> Result<Type, Error> {
+    match callee {
+        Type::Function(fun) => fun.call(args, stg),
+        Type::Builtin(builtin) => builtin.call_builtin(args, stg),
+        Type::Native(native) => native.call(args, stg),
+        _ => Err(Error::NotCallable(callee)),
+    }
+}
+
+/// The value of a quoted expression: lists become `ConsList`s, and
+/// everything else is returned as is.
+pub fn quote(elem: Type) -> Type {
+    match elem {
+        Type::SExpr(list) => Type::ConsList(Vector::from_iter(list.into_iter().map(quote))),
+        elem => elem,
+    }
+}
+
+/// Evaluate a top-level form, with its variables resolved to slots first
+/// as they are in function bodies.
+pub fn eval_top_level(expr: Type, stg: &mut Scope) -> Result<Type, Error> {
+    let expr = resolve_in(expr, stg)?;
+
+    eval(expr, stg)
 }
 
-pub fn eval(expr: Type, mut stg: &mut Scope) -> Type {
+pub fn eval(expr: Type, stg: &mut Scope) -> Result<Type, Error> {
     match expr {
-        Type::Nil => Type::Nil,
-        Type::Quoted(box Type::SExpr(list)) => {
-            let new_list = Vector::from_iter(
-                list.into_iter()
-                    .map(|e| Type::Quoted(Box::new(e)))
-                    .map(|s| eval(s, stg)),
-            );
-            Type::ConsList(new_list)
-        }
-        Type::Quoted(box elem) => elem,
-        Type::Symbol(sym) => stg[&sym].clone(),
+        Type::Nil => Ok(Type::Nil),
+        Type::Quoted(elem) => Ok(quote((*elem).clone())),
+        Type::Symbol(sym) => stg.get(sym),
+        Type::Local(depth, index) => stg.local(depth, index),
+        Type::Global(slot) => stg.global(slot),
+        Type::Lambda(lambda) => Ok(Type::Function(gc::alloc(Function::new(lambda, stg)))),
         Type::SExpr(sexpr) => {
-            let car_eval = eval(sexpr.head().unwrap().clone(), stg);
+            let car_eval = eval(sexpr.head().unwrap().clone(), stg)?;
 
             match car_eval {
-                Type::Macro(mac) => eval(mac.borrow_mut().call_builtin(sexpr.skip(1), stg), stg),
+                Type::Macro(mac) => {
+                    let expansion = mac.call_builtin(sexpr.skip(1), stg)?;
+                    eval(expansion, stg)
+                }
                 Type::Function(fun) => {
                     let args = sexpr
                         .skip(1)
                         .into_iter()
                         .map(|v| eval(v, stg))
-                        .collect::<Vector<Type>>();
+                        .collect::<Result<Vector<Type>, Error>>()?;
 
                     fun.call(args, stg)
                 }
@@ -66,18 +119,101 @@ pub fn eval(expr: Type, mut stg: &mut Scope) -> Type {
                     let mut args = sexpr.skip(1);
 
                     for arg in args.iter_mut() {
-                        *arg = eval(arg.clone(), stg);
+                        *arg = eval(arg.clone(), stg)?;
 
                         // println!("New arg: {:?}", *arg);
                     }
 
                     // println!("FINAL ARGS: {:?}", args);
 
-                    builtin.borrow_mut().call_builtin(args, &mut stg)
+                    builtin.call_builtin(args, stg)
                 }
-                _ => panic!("{:?} is not callable", car_eval),
+                Type::Native(native) => {
+                    let args = sexpr
+                        .skip(1)
+                        .into_iter()
+                        .map(|v| eval(v, stg))
+                        .collect::<Result<Vector<Type>, Error>>()?;
+
+                    native.call(args, stg)
+                }
+                _ => Err(Error::NotCallable(car_eval)),
             }
         }
-        _ => return expr,
+        _ => Ok(expr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::run;
+    use super::*;
+
+    #[test]
+    fn call_defined_function() {
+        assert_eq!(
+            run("(define (add-new x y) (+ x y)) (add-new 1 1)").unwrap(),
+            Type::Int(2)
+        );
+    }
+
+    #[test]
+    fn closure_captures_parameter() {
+        let code = "
+            (define (adder x)
+                (define (add y) (+ x y))
+                add)
+            (define add2 (adder 2))
+            (add2 3)";
+
+        assert_eq!(run(code).unwrap(), Type::Int(5));
+    }
+
+    #[test]
+    fn forward_reference_to_global() {
+        let code = "
+            (define (scaled x) (* x factor))
+            (define factor 3)
+            (scaled 4)";
+
+        assert_eq!(run(code).unwrap(), Type::Int(12));
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(run("(+ -1.5 1)").unwrap(), Type::Float(-0.5));
+        assert_eq!(run("(* 0.5 4)").unwrap(), Type::Float(2.0));
+        assert_eq!(run("(* 2 3 -1)").unwrap(), Type::Int(-6));
+        assert_eq!(run("(add 1 2)").unwrap(), Type::Int(3));
+        assert_eq!(
+            eval_str("(+ 9223372036854775807 1)", &mut create_env()),
+            Err(Error::Message("+: integer overflow".into()))
+        );
+        assert_eq!(
+            eval_str("(* \"2\" 3)", &mut create_env())
+                .unwrap_err()
+                .to_string(),
+            "*: expected a number, got \"2\""
+        );
+    }
+
+    #[test]
+    fn exit_unwinds_with_status() {
+        let mut env = create_env();
+
+        assert_eq!(
+            eval_str("(define (quit) (exit 3) 4) (quit) 5", &mut env),
+            Err(Error::Exit(3))
+        );
+        assert_eq!(
+            eval_str("(exit 4294967297)", &mut env)
+                .unwrap_err()
+                .to_string(),
+            "exit: expected an integer that fits in u8, got 4294967297"
+        );
+        assert!(matches!(
+            eval_str("(exit 1 2)", &mut env),
+            Err(Error::Message(_))
+        ));
     }
 }
@@ -0,0 +1,265 @@
+//! `format`: filling in a control string.
+//!
+//! Directives start with `~`:
+//!
+//! - `~a` the next argument in `display` form
+//! - `~s` the next argument in `write` form
+//! - `~d` the next argument, a number, in decimal
+//! - `~x` the next argument, an integer, in hexadecimal
+//! - `~%` a newline
+//! - `~~` a tilde
+//!
+//! Between the `~` and the letter, options work as in `printf`: a width
+//! pads the output on the left to that many characters, `-` pads on the
+//! right instead, and a leading `0` pads a number with zeros. A precision
+//! after a `.` is the number of decimals `~d` prints floats with, or the
+//! most characters `~a` and `~s` print. `~-10a`, `~08.3d` and `~4x` are
+//! all directives. Neither can be over 1000.
+
+use crate::error::Error;
+use crate::printer;
+use crate::types::Type;
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// The options of a directive.
+#[derive(Debug, Default, PartialEq)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Parse the options, failing if a number is over `MAX_OPTION`.
+    fn parse(chars: &mut Peekable<Chars>) -> Result<Spec, String> {
+        let left = skip(chars, '-');
+        let zero = skip(chars, '0');
+        let width = digits(chars, "width")?.unwrap_or(0);
+        let precision = if skip(chars, '.') {
+            Some(digits(chars, "precision")?.unwrap_or(0))
+        } else {
+            None
+        };
+
+        Ok(Spec {
+            left,
+            zero,
+            width,
+            precision,
+        })
+    }
+
+    /// `text` padded out to the width. Zeros go after a number's sign.
+    fn pad(&self, text: String, numeric: bool) -> String {
+        let len = text.chars().count();
+
+        if len >= self.width {
+            return text;
+        }
+
+        let fill = self.width - len;
+
+        if self.left {
+            text + &" ".repeat(fill)
+        } else if self.zero && numeric {
+            let sign = if text.starts_with('-') { 1 } else { 0 };
+            format!("{}{}{}", &text[..sign], "0".repeat(fill), &text[sign..])
+        } else {
+            " ".repeat(fill) + &text
+        }
+    }
+
+    /// `text` cut down to the precision.
+    fn truncate(&self, text: String) -> String {
+        match self.precision {
+            Some(precision) => text.chars().take(precision).collect(),
+            None => text,
+        }
+    }
+}
+
+/// Skip `expected` if it's the next char.
+fn skip(chars: &mut Peekable<Chars>, expected: char) -> bool {
+    let found = chars.peek() == Some(&expected);
+
+    if found {
+        chars.next();
+    }
+
+    found
+}
+
+/// The largest width or precision a directive can give.
+const MAX_OPTION: usize = 1000;
+
+/// The number at the start of `chars`, if there is one. `what` names it in
+/// the error if it's over `MAX_OPTION`.
+fn digits(chars: &mut Peekable<Chars>, what: &str) -> Result<Option<usize>, String> {
+    let mut number = None;
+
+    while let Some(digit) = chars.peek().and_then(|ch| ch.to_digit(10)) {
+        chars.next();
+
+        let next = number.unwrap_or(0) * 10 + digit as usize;
+
+        if next > MAX_OPTION {
+            return Err(format!("{} too large", what));
+        }
+
+        number = Some(next);
+    }
+
+    Ok(number)
+}
+
+/// Fill in `control` with `args`, for the builtin `name`.
+pub fn format(name: &str, control: &str, args: &[Type]) -> Result<String, Error> {
+    let error = |msg: String| Error::Message(format!("{}: {}", name, msg));
+
+    let mut out = String::with_capacity(control.len());
+    let mut args = args.iter();
+    let mut chars = control.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch != '~' {
+            out.push(ch);
+            continue;
+        }
+
+        let spec = Spec::parse(&mut chars).map_err(error)?;
+        let directive = chars
+            .next()
+            .ok_or_else(|| error("control string ends in an unfinished directive".into()))?;
+        let mut next_arg = || {
+            args.next()
+                .ok_or_else(|| error(format!("not enough arguments for ~{}", directive)))
+        };
+
+        let text = match directive.to_ascii_lowercase() {
+            'a' => spec.pad(spec.truncate(printer::display(next_arg()?)), false),
+            's' => spec.pad(spec.truncate(printer::write(next_arg()?)), false),
+            'd' => match (next_arg()?, spec.precision) {
+                (Type::Int(int), _) => spec.pad(int.to_string(), true),
+                (Type::Float(flo), Some(precision)) => {
+                    spec.pad(format!("{:.*}", precision, flo), true)
+                }
+                (number @ Type::Float(_), None) => spec.pad(printer::display(number), true),
+                (got, _) => return Err(error(format!("~d expected a number, got {:#}", got))),
+            },
+            'x' => match next_arg()? {
+                Type::Int(int) if *int < 0 => spec.pad(format!("-{:x}", -i128::from(*int)), true),
+                Type::Int(int) => spec.pad(format!("{:x}", int), true),
+                got => return Err(error(format!("~x expected an integer, got {:#}", got))),
+            },
+            '%' => "\n".into(),
+            '~' => "~".into(),
+            _ => return Err(error(format!("unknown directive ~{}", directive))),
+        };
+
+        out.push_str(&text);
+    }
+
+    if args.next().is_some() {
+        return Err(error("too many arguments".into()));
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(control: &str, args: &[Type]) -> Result<String, String> {
+        format("format", control, args).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn directives() {
+        let hi = Type::StrLit("hi".into());
+
+        assert_eq!(fmt("~a/~s", &[hi.clone(), hi]), Ok("hi/\"hi\"".into()));
+        assert_eq!(fmt("~~~%", &[]), Ok("~\n".into()));
+        assert_eq!(fmt("~A", &[Type::Int(1)]), Ok("1".into()));
+        assert_eq!(
+            fmt(
+                "~d ~d ~x",
+                &[Type::Int(-7), Type::Float(2.0), Type::Int(255)]
+            ),
+            Ok("-7 2.0 ff".into())
+        );
+        assert_eq!(fmt("~x", &[Type::Int(-255)]), Ok("-ff".into()));
+    }
+
+    #[test]
+    fn options() {
+        let hi = || Type::StrLit("hi".into());
+
+        assert_eq!(fmt("[~5a]", &[hi()]), Ok("[   hi]".into()));
+        assert_eq!(fmt("[~-5s]", &[hi()]), Ok("[\"hi\" ]".into()));
+        assert_eq!(fmt("[~.1a]", &[hi()]), Ok("[h]".into()));
+        assert_eq!(fmt("~05d", &[Type::Int(-42)]), Ok("-0042".into()));
+        assert_eq!(fmt("~.2d", &[Type::Float(1.256)]), Ok("1.26".into()));
+        assert_eq!(fmt("~08.3d", &[Type::Float(-1.5)]), Ok("-001.500".into()));
+        assert_eq!(fmt("~04x", &[Type::Int(255)]), Ok("00ff".into()));
+        assert_eq!(fmt("~3d", &[Type::Int(12345)]), Ok("12345".into()));
+    }
+
+    #[test]
+    fn specs() {
+        let parse = |text: &str| Spec::parse(&mut text.chars().peekable());
+
+        assert_eq!(parse("a"), Ok(Spec::default()));
+        assert_eq!(
+            parse("-012.3d"),
+            Ok(Spec {
+                left: true,
+                zero: true,
+                width: 12,
+                precision: Some(3),
+            })
+        );
+        assert_eq!(parse("1000a").map(|spec| spec.width), Ok(1000));
+        assert_eq!(parse("1001a"), Err("width too large".into()));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            fmt("~99999999999999999999a", &[Type::Int(1)]),
+            Err("format: width too large".into())
+        );
+        assert_eq!(
+            fmt("~.9999999999d", &[Type::Float(1.0)]),
+            Err("format: precision too large".into())
+        );
+        assert_eq!(
+            fmt("~a ~a", &[Type::Int(1)]),
+            Err("format: not enough arguments for ~a".into())
+        );
+        assert_eq!(
+            fmt("", &[Type::Int(1)]),
+            Err("format: too many arguments".into())
+        );
+        assert_eq!(fmt("~q", &[]), Err("format: unknown directive ~q".into()));
+        assert_eq!(
+            fmt("50~", &[]),
+            Err("format: control string ends in an unfinished directive".into())
+        );
+        assert_eq!(
+            fmt("~5", &[]),
+            Err("format: control string ends in an unfinished directive".into())
+        );
+        assert_eq!(
+            fmt("~d", &[Type::StrLit("1".into())]),
+            Err("format: ~d expected a number, got \"1\"".into())
+        );
+        assert_eq!(
+            fmt("~x", &[Type::Float(1.5)]),
+            Err("format: ~x expected an integer, got 1.5".into())
+        );
+    }
+}
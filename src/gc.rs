@@ -0,0 +1,379 @@
+//! A cycle collector for the heap objects behind `Type`.
+//!
+//! Values are reference counted, so almost everything is freed as soon as
+//! it's dropped. What reference counting can't free is a cycle, such as a
+//! closure stored in the frame it captured. Every frame and function is
+//! registered with a per-thread `Heap`, and a collection finds the ones only
+//! kept alive by each other:
+//!
+//! 1. For each tracked object, count the references to it held by other
+//!    tracked objects.
+//! 2. Any object with more strong references than that is held from outside
+//!    the heap (a global, the Rust stack, the host) and is a root.
+//! 3. Everything reachable from a root survives. The rest is garbage, and is
+//!    cleared so its cycles fall apart and reference counting frees them.
+//!
+//! References held inside persistent lists are only followed when marking:
+//! their storage may be shared with values outside the heap, so they never
+//! count towards step 1. That keeps collection safe at any allocation, at
+//! the cost of leaving cycles that pass through a list.
+
+use crate::types::Type;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// A heap object the collector can see into.
+pub trait Trace {
+    /// Report every value `self` holds to `tracer`.
+    ///
+    /// Only report a value through `Tracer::value` or `Tracer::edge` if
+    /// `self` is the only owner of the storage it sits in. A reference in
+    /// storage that can be shared, such as an `im::Vector` handed out to
+    /// other values, is also held from outside `self`; counting it as owned
+    /// makes the collector free an object that is still in use.
+    fn trace(&self, tracer: &mut Tracer);
+
+    /// Drop the references `self` holds, so a garbage cycle through it falls
+    /// apart.
+    fn clear(&self);
+
+    /// Roughly how many bytes `self` keeps alive, excluding other objects.
+    fn size(&self) -> usize;
+}
+
+/// Collects the edges from one object to other tracked objects.
+#[derive(Default)]
+pub struct Tracer {
+    /// The address of each referenced object, and whether the reference is
+    /// owned by the object being traced rather than shared storage.
+    edges: Vec<(usize, bool)>,
+}
+
+impl Tracer {
+    /// A strong reference owned directly by the object being traced.
+    pub fn edge<T: ?Sized>(&mut self, rc: &Rc<T>) {
+        self.edges.push((address(rc), true));
+    }
+
+    /// A value owned directly by the object being traced.
+    pub fn value(&mut self, value: &Type) {
+        self.walk(value, true);
+    }
+
+    fn walk(&mut self, value: &Type, owned: bool) {
+        match value {
+            Type::Function(fun) => self.edges.push((address(fun), owned)),
+            Type::Record(record) => self.edges.push((address(record), owned)),
+            Type::HashMap(table) => self.edges.push((address(table), owned)),
+            Type::Vector(vector) => self.edges.push((address(vector), owned)),
+            Type::Cons(list) | Type::ConsList(list) | Type::SExpr(list) => {
+                for value in list {
+                    self.walk(value, false);
+                }
+            }
+            Type::Quoted(quoted) => self.walk(quoted, false),
+            _ => {}
+        }
+    }
+}
+
+fn address<T: ?Sized>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+/// Collector statistics, as returned by `stats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+    /// Tracked objects still alive.
+    pub live: usize,
+    /// Approximate bytes held by the live objects.
+    pub bytes: usize,
+    /// Collections run so far.
+    pub collections: usize,
+    /// Objects freed by collections so far.
+    pub freed: usize,
+}
+
+const MIN_THRESHOLD: usize = 1024;
+
+struct Heap {
+    objects: Vec<Weak<dyn Trace>>,
+    /// Allocations left before the next automatic collection.
+    countdown: usize,
+    collections: usize,
+    freed: usize,
+}
+
+impl Default for Heap {
+    fn default() -> Heap {
+        Heap {
+            objects: Vec::new(),
+            countdown: MIN_THRESHOLD,
+            collections: 0,
+            freed: 0,
+        }
+    }
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap::default());
+}
+
+/// Allocate `value` on the tracked heap, collecting first if enough has been
+/// allocated since the last collection.
+pub fn alloc<T: Trace + 'static>(value: T) -> Rc<T> {
+    let due = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+
+        heap.countdown = heap.countdown.saturating_sub(1);
+        heap.countdown == 0
+    });
+
+    if due {
+        collect();
+    }
+
+    let rc = Rc::new(value);
+    let weak: Weak<dyn Trace> = Rc::downgrade(&rc) as Weak<dyn Trace>;
+
+    HEAP.with(|heap| heap.borrow_mut().objects.push(weak));
+
+    rc
+}
+
+/// Run a collection, returning how many objects it freed.
+pub fn collect() -> usize {
+    // Take the objects out so that anything freed while clearing can't
+    // re-enter the heap while it's borrowed.
+    let objects: Vec<Rc<dyn Trace>> = HEAP.with(|heap| {
+        heap.borrow_mut()
+            .objects
+            .drain(..)
+            .filter_map(|weak| weak.upgrade())
+            .collect()
+    });
+
+    let index: HashMap<usize, usize> = objects
+        .iter()
+        .enumerate()
+        .map(|(i, object)| (address(object), i))
+        .collect();
+
+    let mut children: Vec<Vec<usize>> = Vec::with_capacity(objects.len());
+    let mut internal = vec![0; objects.len()];
+
+    for object in &objects {
+        let mut tracer = Tracer::default();
+        object.trace(&mut tracer);
+
+        let mut edges = Vec::new();
+
+        for (addr, owned) in tracer.edges {
+            if let Some(&child) = index.get(&addr) {
+                if owned {
+                    internal[child] += 1;
+                }
+
+                edges.push(child);
+            }
+        }
+
+        children.push(edges);
+    }
+
+    // `objects` holds one strong reference to each object itself.
+    let mut marked: Vec<bool> = objects
+        .iter()
+        .zip(&internal)
+        .map(|(object, &internal)| Rc::strong_count(object) - 1 > internal)
+        .collect();
+
+    let mut pending: Vec<usize> = (0..objects.len()).filter(|&i| marked[i]).collect();
+
+    while let Some(i) = pending.pop() {
+        for &child in &children[i] {
+            if !marked[child] {
+                marked[child] = true;
+                pending.push(child);
+            }
+        }
+    }
+
+    let mut freed = 0;
+    let mut live = Vec::new();
+
+    for (object, marked) in objects.into_iter().zip(marked) {
+        if marked {
+            live.push(Rc::downgrade(&object));
+        } else {
+            object.clear();
+            freed += 1;
+        }
+    }
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+
+        // Anything allocated while clearing was pushed onto the empty list.
+        live.append(&mut heap.objects);
+        heap.countdown = MIN_THRESHOLD.max(live.len() * 2);
+        heap.objects = live;
+        heap.collections += 1;
+        heap.freed += freed;
+    });
+
+    freed
+}
+
+pub fn stats() -> Stats {
+    HEAP.with(|heap| {
+        let heap = heap.borrow();
+        let live: Vec<Rc<dyn Trace>> = heap.objects.iter().filter_map(Weak::upgrade).collect();
+
+        Stats {
+            live: live.len(),
+            bytes: live.iter().map(|object| object.size()).sum(),
+            collections: heap.collections,
+            freed: heap.freed,
+        }
+    })
+}
+
+thread_local! {
+    /// The objects being visited by `visit`, innermost last.
+    static VISITING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+
+    /// The pairs of objects being compared by `equal`, innermost last.
+    static COMPARING: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Pops the innermost entry of a stack when dropped, even on a panic.
+struct Popped<T: 'static>(&'static std::thread::LocalKey<RefCell<Vec<T>>>);
+
+impl<T> Drop for Popped<T> {
+    fn drop(&mut self) {
+        self.0.with(|stack| stack.borrow_mut().pop());
+    }
+}
+
+/// Run `f` on the condition that `object` isn't already being visited
+/// further up, as it is when a value reaches itself through a cycle.
+/// Returns `None` in that case, so printing and hashing can stop there.
+pub fn visit<T: ?Sized, R>(object: &T, f: impl FnOnce() -> R) -> Option<R> {
+    let addr = object as *const T as *const () as usize;
+
+    let entered = VISITING.with(|visiting| {
+        let mut visiting = visiting.borrow_mut();
+
+        if visiting.contains(&addr) {
+            return false;
+        }
+
+        visiting.push(addr);
+        true
+    });
+
+    if !entered {
+        return None;
+    }
+
+    let _popped = Popped(&VISITING);
+    Some(f())
+}
+
+/// Compare two objects with `eq`, treating them as equal if they're the same
+/// object, or if the same pair is already being compared further up. Two
+/// cyclic values are then equal when nothing along the cycle differs.
+pub fn equal<T: ?Sized>(a: &T, b: &T, eq: impl FnOnce() -> bool) -> bool {
+    if std::ptr::eq(a, b) {
+        return true;
+    }
+
+    let pair = (
+        a as *const T as *const () as usize,
+        b as *const T as *const () as usize,
+    );
+
+    let entered = COMPARING.with(|comparing| {
+        let mut comparing = comparing.borrow_mut();
+
+        if comparing.contains(&pair) {
+            return false;
+        }
+
+        comparing.push(pair);
+        true
+    });
+
+    if !entered {
+        return true;
+    }
+
+    let _popped = Popped(&COMPARING);
+    eq()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::error::Error;
+    use crate::eval::testing::run;
+    use crate::eval::{create_env, eval, eval_str};
+    use crate::symbol::Symbol;
+
+    #[test]
+    fn collect_closure_cycle() {
+        collect();
+        let before = stats().live;
+
+        // Each call leaves `inner` in the frame it closes over.
+        run("(define (outer x) (define (inner) x) x) (outer 1) (outer 2)").unwrap();
+
+        assert!(stats().live > before);
+        collect();
+        assert_eq!(stats().live, before);
+    }
+
+    #[test]
+    fn gc_builtins() {
+        let mut env = create_env();
+        let mut eval = |code: &str| eval_str(code, &mut env).unwrap();
+
+        eval("(define (outer x) (define (inner) x) x)");
+        collect();
+
+        // The call leaves a garbage cycle of `outer`'s frame and `inner`.
+        eval("(outer 1)");
+
+        assert_eq!(eval("(gc)"), Type::Int(2));
+        assert_eq!(eval("(gc)"), Type::Int(0));
+        match &eval("(gc-stats)") {
+            Type::ConsList(entries) => assert_eq!(entries.len(), 4),
+            t => panic!("expected a list, got {:?}", t),
+        }
+    }
+
+    #[test]
+    fn gc_builtins_take_no_arguments() {
+        for code in &["(gc 1 2 3)", "(gc-stats 1)"] {
+            assert!(matches!(run(code), Err(Error::Arity { expected: 0, .. })));
+        }
+    }
+
+    #[test]
+    fn keep_reachable_closure() {
+        let code = "(define (adder x) (define (add y) (+ x y)) add) (define add2 (adder 2))";
+        let mut env = create_env();
+        eval_str(code, &mut env).unwrap();
+
+        collect();
+
+        let add2 = env.get(Symbol::intern("add2")).unwrap();
+        let call = Type::SExpr(vec![add2, Type::Int(3)].into_iter().collect());
+
+        assert_eq!(eval(call, &mut env), Ok(Type::Int(5)));
+    }
+}
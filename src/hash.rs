@@ -0,0 +1,319 @@
+//! Hash tables: mutable maps from any value to any value.
+//!
+//! A table holds a persistent `im::HashMap`, so copying one out for
+//! `hash->alist` and friends is cheap. Keys compare like `equal?`: two lists
+//! with equal elements are the same key.
+//!
+//! Tables read as `#hash((key . value) ...)`, with the keys and values taken
+//! as data, the way `quote` takes them. The builtins refuse to modify a
+//! literal; the README's "Literals" section says why.
+
+use crate::convert::FromLisp;
+use crate::error::Error;
+use crate::gc::{self, Trace, Tracer};
+use crate::types::Type;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
+
+/// A value used as a key, hashed by structure so that equal values hash
+/// alike.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Key(pub Type);
+
+// `Type` is only `PartialEq` because of floats. A NaN key can be inserted
+// but never found again, as with `equal?`.
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_value(&self.0, state);
+    }
+}
+
+fn hash_value<H: Hasher>(value: &Type, state: &mut H) {
+    mem::discriminant(value).hash(state);
+
+    match value {
+        Type::Int(int) => int.hash(state),
+        // `0.0` and `-0.0` are equal, so they have to hash alike.
+        Type::Float(flo) if *flo == 0.0 => 0u64.hash(state),
+        Type::Float(flo) => flo.to_bits().hash(state),
+        Type::Bool(b) => b.hash(state),
+        Type::StrLit(string) => string.hash(state),
+        Type::Symbol(sym) => sym.hash(state),
+        Type::Cons(list) | Type::ConsList(list) | Type::SExpr(list) => {
+            list.len().hash(state);
+
+            for elem in list {
+                hash_value(elem, state);
+            }
+        }
+        Type::Quoted(quoted) => hash_value(quoted, state),
+        // A container met again inside itself adds nothing more.
+        Type::Vector(vector) => {
+            gc::visit(&**vector, || {
+                vector.len().hash(state);
+
+                for elem in vector.elems().iter() {
+                    hash_value(elem, state);
+                }
+            });
+        }
+        Type::Record(record) => {
+            gc::visit(&**record, || {
+                for field in record.values() {
+                    hash_value(&field, state);
+                }
+            });
+        }
+        // Everything else hashes by kind alone, which is still consistent
+        // with equality.
+        _ => {}
+    }
+}
+
+pub struct HashTable {
+    map: RefCell<im::HashMap<Key, Type>>,
+    literal: bool,
+}
+
+impl HashTable {
+    /// A tracked table holding `entries`.
+    pub fn new(entries: impl IntoIterator<Item = (Type, Type)>) -> Rc<HashTable> {
+        HashTable::alloc(entries, false)
+    }
+
+    /// A tracked table read from a `#hash(...)` literal.
+    pub fn literal(entries: impl IntoIterator<Item = (Type, Type)>) -> Rc<HashTable> {
+        HashTable::alloc(entries, true)
+    }
+
+    fn alloc(entries: impl IntoIterator<Item = (Type, Type)>, literal: bool) -> Rc<HashTable> {
+        let map = entries
+            .into_iter()
+            .map(|(key, value)| (Key(key), value))
+            .collect();
+
+        gc::alloc(HashTable {
+            map: RefCell::new(map),
+            literal,
+        })
+    }
+
+    /// Whether the table was read from a literal, and shouldn't be modified.
+    pub fn is_literal(&self) -> bool {
+        self.literal
+    }
+
+    pub fn get(&self, key: &Type) -> Option<Type> {
+        self.map.borrow().get(&Key(key.clone())).cloned()
+    }
+
+    // `insert` and `remove` change a copy of the map and store it back, since
+    // comparing keys can borrow the map again: a table may be its own key.
+
+    pub fn insert(&self, key: Type, value: Type) {
+        let mut map = self.map.borrow().clone();
+        map.insert(Key(key), value);
+        *self.map.borrow_mut() = map;
+    }
+
+    pub fn remove(&self, key: &Type) -> Option<Type> {
+        let mut map = self.map.borrow().clone();
+        let removed = map.remove(&Key(key.clone()));
+        *self.map.borrow_mut() = map;
+        removed
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.borrow().is_empty()
+    }
+
+    /// The entries, in no particular order.
+    pub fn entries(&self) -> Vec<(Type, Type)> {
+        self.map
+            .borrow()
+            .iter()
+            .map(|(key, value)| (key.0.clone(), value.clone()))
+            .collect()
+    }
+}
+
+/// Tables are equal if they have equal keys mapping to equal values.
+impl PartialEq for HashTable {
+    fn eq(&self, other: &HashTable) -> bool {
+        gc::equal(self, other, || *self.map.borrow() == *other.map.borrow())
+    }
+}
+
+impl fmt::Debug for HashTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HashTable {:?}", self.map.borrow())
+    }
+}
+
+impl Trace for HashTable {
+    fn trace(&self, tracer: &mut Tracer) {
+        for (key, value) in self.map.borrow().iter() {
+            tracer.value(&key.0);
+            tracer.value(value);
+        }
+    }
+
+    fn clear(&self) {
+        *self.map.borrow_mut() = im::HashMap::new();
+    }
+
+    fn size(&self) -> usize {
+        mem::size_of::<HashTable>() + self.len() * 2 * mem::size_of::<Type>()
+    }
+}
+
+impl FromLisp for Rc<HashTable> {
+    fn from_lisp(value: Type) -> Result<Rc<HashTable>, Error> {
+        match value {
+            Type::HashMap(table) => Ok(table),
+            got => Err(Error::Conversion {
+                expected: "a hash table",
+                got,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::eval::testing::run;
+    use crate::eval::{create_env, eval_str};
+
+    #[test]
+    fn structural_keys() {
+        let table = HashTable::new(vec![]);
+        let list = |items: Vec<i64>| Type::ConsList(items.into_iter().map(Type::Int).collect());
+
+        table.insert(list(vec![1, 2]), Type::Int(3));
+        table.insert(Type::Float(0.0), Type::Int(0));
+
+        assert_eq!(table.get(&list(vec![1, 2])), Some(Type::Int(3)));
+        assert_eq!(table.get(&Type::Float(-0.0)), Some(Type::Int(0)));
+        assert_eq!(table.get(&Type::Int(0)), None);
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn builtins() {
+        let mut env = create_env();
+        let code = "
+            (define t (make-hash-table))
+            (hash-set! t 'a 1)
+            (hash-set! t \"b\" 2)
+            (hash-set! t '(1 2) 3)
+            (hash-remove! t \"b\")";
+        eval_str(code, &mut env).unwrap();
+
+        let mut eval = |code: &str| eval_str(code, &mut env).unwrap();
+
+        assert_eq!(eval("(hash-ref t 'a)"), Type::Int(1));
+        assert_eq!(eval("(hash-ref t '(1 2))"), Type::Int(3));
+        assert_eq!(
+            eval("(hash-ref t \"b\" 'none)"),
+            Type::Symbol("none".into())
+        );
+        assert_eq!(eval("(hash-count t)"), Type::Int(2));
+        eval("(hash-remove! t 'a)");
+        assert_eq!(eval("(hash-values t)").to_string(), "(3)");
+        assert_eq!(eval("(hash-keys t)").to_string(), "((1 2))");
+        assert_eq!(
+            run("(hash-ref (make-hash-table) 'a)")
+                .unwrap_err()
+                .to_string(),
+            "hash-ref: no value for the key a"
+        );
+        assert_eq!(
+            run("(hash-count 5)").unwrap_err().to_string(),
+            "hash-count: expected a hash table, got 5"
+        );
+    }
+
+    #[test]
+    fn table_keys() {
+        let mut env = create_env();
+        let code = "
+            (define h (make-hash-table))
+            (hash-set! h (make-hash-table) 1)
+            (hash-set! h h 2)";
+        eval_str(code, &mut env).unwrap();
+
+        let mut eval = |code: &str| eval_str(code, &mut env).unwrap();
+
+        assert_eq!(eval("(hash-ref h h)"), Type::Int(2));
+        assert_eq!(eval("(hash-ref h (make-hash-table))"), Type::Int(1));
+        assert_eq!(eval("(hash-count h)"), Type::Int(2));
+        assert_eq!(eval("(equal? h h)"), Type::Bool(true));
+        assert_eq!(eval("(hash-remove! h h) (hash-count h)"), Type::Int(1));
+    }
+
+    #[test]
+    fn print_self_containing_table() {
+        assert_eq!(
+            run("(define h (make-hash-table)) (hash-set! h 'me h) h").map(|v| v.to_string()),
+            Ok("#hash((me . #hash(...)))".into())
+        );
+    }
+
+    #[test]
+    fn literals_are_constant() {
+        let code = "(define (fresh) #hash((a . 1))) (hash-set! (fresh) 'b 2)";
+
+        assert_eq!(
+            run(code).unwrap_err().to_string(),
+            "hash-set!: can't modify a literal hash table"
+        );
+        assert_eq!(
+            run("(hash-remove! #hash((a . 1)) 'a)")
+                .unwrap_err()
+                .to_string(),
+            "hash-remove!: can't modify a literal hash table"
+        );
+        assert_eq!(
+            run("(define (fresh) #hash()) (hash-count (fresh))"),
+            Ok(Type::Int(0))
+        );
+    }
+
+    #[test]
+    fn literal() {
+        assert_eq!(
+            run("(hash-ref #hash((a . 1) ((1 2) . \"x\")) '(1 2))"),
+            Ok(Type::StrLit("x".into()))
+        );
+        assert_eq!(
+            run("(hash->alist #hash((a . (b c))))").map(|v| v.to_string()),
+            Ok("((a . (b c)))".into())
+        );
+        assert_eq!(
+            run("(equal? (hash->alist #hash((a . (b c)))) '((a . (b c))))"),
+            Ok(Type::Bool(true))
+        );
+        assert_eq!(
+            run("#hash((\"k\" . 1))").map(|v| format!("{:#}", v)),
+            Ok("#hash((\"k\" . 1))".into())
+        );
+        assert_eq!(run("(hash-count #hash())"), Ok(Type::Int(0)));
+        assert_eq!(
+            run("(equal? #hash((a . 1) (b . 2)) #hash((b . 2) (a . 1)))"),
+            Ok(Type::Bool(true))
+        );
+        assert!(run("#hash((a 1))").is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! The embedding API: one `Interpreter` owns a global environment and
+//! evaluates code in it.
+
+pub use crate::types::Type as Value;
+
+use crate::convert::IntoBuiltin;
+use crate::error::Error;
+use crate::eval::{self, apply, create_env};
+use crate::lexer::Lexer;
+use crate::load;
+use crate::parser::parse;
+use crate::symbol::Symbol;
+use crate::types::Scope;
+use crate::vm;
+
+use std::path::Path;
+use std::rc::Rc;
+
+/// How an `Interpreter` runs code. Both backends give the same results; the
+/// tree-walker is the reference implementation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// The tree-walking evaluator in `eval`.
+    #[default]
+    TreeWalk,
+    /// Compile to bytecode and run it on the stack machine in `vm`.
+    Bytecode,
+}
+
+impl Backend {
+    /// Evaluate the top-level form `expr` in `env`.
+    pub fn eval(self, expr: Value, env: &mut Scope) -> Result<Value, Error> {
+        match self {
+            Backend::TreeWalk => eval::eval_top_level(expr, env),
+            Backend::Bytecode => vm::eval(expr, env),
+        }
+    }
+}
+
+pub struct Interpreter {
+    env: Scope,
+    backend: Backend,
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    /// An interpreter with the builtins defined. The same as `with_stdlib`.
+    pub fn new() -> Interpreter {
+        Interpreter::with_stdlib()
+    }
+
+    /// An interpreter with every builtin in the registry: the core ones, and
+    /// those of any plugin crate linked into the program.
+    pub fn with_stdlib() -> Interpreter {
+        Interpreter::with_backend(Backend::default())
+    }
+
+    /// An interpreter like `with_stdlib` that runs code on `backend`.
+    pub fn with_backend(backend: Backend) -> Interpreter {
+        Interpreter {
+            env: create_env(),
+            backend,
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    /// Evaluate one parsed expression.
+    pub fn eval(&mut self, expr: Value) -> Result<Value, Error> {
+        self.backend.eval(expr, &mut self.env)
+    }
+
+    /// Evaluate every expression in `source`, returning the value of the
+    /// last one.
+    pub fn eval_str(&mut self, source: &str) -> Result<Value, Error> {
+        let mut lexer = Lexer::new(source);
+        let mut value = Value::Nil;
+
+        for expr in parse(&mut lexer) {
+            value = self.eval(expr?)?;
+        }
+
+        Ok(value)
+    }
+
+    /// Evaluate the file at `path`, returning the value of its last
+    /// expression.
+    pub fn eval_file(&mut self, path: impl AsRef<Path>) -> Result<Value, Error> {
+        let source = load::read(path.as_ref())?;
+
+        self.eval_str(&source)
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.env.put(Symbol::intern(name), value);
+    }
+
+    /// Define the global `name` as a builtin that calls `fun`.
+    ///
+    /// Arguments are converted with `FromLisp` and the result with
+    /// `IntoLisp`; `fun` may also return a `Result<_, Error>`. Calls with
+    /// the wrong number of arguments, or an argument that doesn't convert,
+    /// fail with an error naming the builtin.
+    pub fn register<Args>(&mut self, name: &str, fun: impl IntoBuiltin<Args>) {
+        let builtin = fun.into_builtin(name);
+        self.define_global(name, Value::Builtin(Rc::new(builtin)));
+    }
+
+    /// The value of the global `name`, if it's bound.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.env.get(Symbol::intern(name)).ok()
+    }
+
+    /// Call the procedure bound to the global `name` with `args`.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        let callee = self.env.get(Symbol::intern(name))?;
+
+        apply(callee, args.into_iter().collect(), &mut self.env)
+    }
+
+    /// The global environment, for the lower-level APIs.
+    pub fn env(&self) -> &Scope {
+        &self.env
+    }
+
+    pub fn env_mut(&mut self) -> &mut Scope {
+        &mut self.env
+    }
+}
@@ -4,12 +4,21 @@ use std::str::CharIndices;
 pub enum Token<'a> {
     LParen,
     RParen,
+    /// `#hash(`, opening a hash table literal.
+    HashParen,
+    /// `#(`, opening a vector literal.
+    VectorParen,
     Quote,
     Float(&'a str),
     Int(&'a str),
     Symbol(&'a str),
+    BarSymbol(&'a str),
     StrLit(&'a str),
     Comment(&'a str),
+    /// A string or `|symbol|` missing its closing delimiter.
+    Unterminated(&'a str),
+    /// A character that can't start any token.
+    Invalid(&'a str),
 }
 
 #[derive(Debug)]
@@ -33,7 +42,11 @@ impl<'a> Lexer<'a> {
 
         lex.next_char();
 
-        // lex.
+        // Skip a `#!` line, so scripts can be run directly.
+        if source.starts_with("#!") {
+            lex.rest_of_line();
+        }
+
         lex
     }
 
@@ -69,15 +82,17 @@ impl<'a> Lexer<'a> {
         token
     }
 
-    fn looking_at(&self, prefix: &'a str) -> bool {
-        self.source[self.pos..].starts_with(prefix)
+    /// Scan `prefix`, which the source is known to continue with.
+    fn scan_prefix(&mut self, prefix: &str, token: Token<'a>) -> Token<'a> {
+        for _ in prefix.chars() {
+            self.next_char();
+        }
+
+        token
     }
 
     fn looking_at_str(&self) -> bool {
-        match self.lookahead {
-            Some('"') => true,
-            _ => false,
-        }
+        self.lookahead == Some('"')
     }
 
     fn rest_of_line(&mut self) -> &'a str {
@@ -99,51 +114,63 @@ impl<'a> Lexer<'a> {
         Token::Comment(text)
     }
 
-    fn scan_string(&mut self) -> Token<'a> {
+    /// Scan text between the delimiter under the cursor and the next
+    /// unescaped one, returning the text with its escapes left in, or `None`
+    /// if the source ends first.
+    fn scan_delimited(&mut self, delim: char) -> Option<&'a str> {
         let start = self.pos;
 
-        assert!(self.looking_at_str());
-
         loop {
             match self.next_char() {
-                Some('"') => break,
-                Some(_) if self.looking_at("\\\"") => {
-                    // We're looking at a \, and know that the next
-                    // char is a ", so consume both, the " will be
-                    // consumed on the next iteration;
-                    assert_eq!(self.next_char(), Some('"'));
+                Some(c) if c == delim => break,
+                Some('\\') => {
+                    // Skip the escaped char, so that neither an escaped
+                    // delimiter nor `\\` can end the text early.
+                    self.next_char();
                 }
-                None => panic!("Malformed string"),
+                None => return None,
                 _ => {}
             }
         }
 
-        // Consume the end quote
+        // Consume the end delimiter
         self.next_char();
 
-        let pre_text = &self.source[start + 1..self.pos - 1];
+        Some(&self.source[start + 1..self.pos - 1])
+    }
+
+    fn scan_string(&mut self) -> Token<'a> {
+        assert!(self.looking_at_str());
+
+        let start = self.pos;
+
+        match self.scan_delimited('"') {
+            Some(text) => Token::StrLit(text),
+            None => Token::Unterminated(&self.source[start..]),
+        }
+    }
+
+    fn scan_bar_symbol(&mut self) -> Token<'a> {
+        let start = self.pos;
 
-        Token::StrLit(pre_text)
+        match self.scan_delimited('|') {
+            Some(text) => Token::BarSymbol(text),
+            None => Token::Unterminated(&self.source[start..]),
+        }
     }
 
     fn scan_number(&mut self) -> Token<'a> {
         let start = self.pos;
         let mut is_float = false;
 
-        match self.lookahead {
-            Some('-') => {
-                self.next_char();
-                if !self.looking_at_numeric() {
-                    return Token::Symbol("-");
-                }
-            }
-            Some('+') => {
-                self.next_char();
-                if !self.looking_at_numeric() {
-                    return Token::Symbol("+");
-                }
+        if let Some('-') | Some('+') = self.lookahead {
+            self.next_char();
+
+            // Without digits after it, the sign starts a symbol such as `+`
+            // or `->list`.
+            if !self.looking_at_numeric() {
+                return self.rest_of_symbol(start);
             }
-            _ => {}
         }
 
         if let Some('.') = self.lookahead {
@@ -173,39 +200,23 @@ impl<'a> Lexer<'a> {
     }
 
     fn looking_at_numeric(&self) -> bool {
-        if let Some(c) = self.lookahead {
-            if c.is_digit(10) {
-                return true;
-            }
-            match c {
-                '-' | '+' | '.' => return true,
-                _ => {}
-            }
+        match self.lookahead {
+            Some(c) if c.is_ascii_digit() => true,
+            Some('.') => self.source[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit()),
+            _ => false,
         }
-
-        false
     }
 
-    fn allowed_in_symbol(ch: &char) -> bool {
+    pub(crate) fn allowed_in_symbol(ch: &char) -> bool {
         if ch.is_ascii_whitespace() {
             return false;
         }
 
-        match ch {
-            '\'' | '\"' | ';' | '(' | ')' => false,
-            _ => true,
-        }
+        !matches!(ch, '\'' | '\"' | ';' | '(' | ')')
     }
 
     pub fn looking_at_symbol(&self) -> bool {
-        if let Some(c) = self.lookahead {
-            match c {
-                '.' | _ if c.is_numeric() => false,
-                _ => true,
-            }
-        } else {
-            false
-        }
+        matches!(self.lookahead, Some(c) if !c.is_numeric())
     }
 
     fn scan_symbol(&mut self) -> Token<'a> {
@@ -225,6 +236,20 @@ impl<'a> Lexer<'a> {
         Token::Symbol(text)
     }
 
+    /// Finish a symbol that began at `start`, up to the cursor.
+    fn rest_of_symbol(&mut self, start: usize) -> Token<'a> {
+        while let Some(c) = self.lookahead {
+            if !Lexer::allowed_in_symbol(&c) {
+                break;
+            }
+
+            self.next_char();
+        }
+
+        Token::Symbol(&self.source[start..self.pos])
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<Token<'a>> {
         loop {
             return match self.lookahead {
@@ -237,12 +262,22 @@ impl<'a> Lexer<'a> {
                 Some(';') => Some(self.scan_comment()),
                 Some('(') => Some(self.scan_char(Token::LParen)),
                 Some(')') => Some(self.scan_char(Token::RParen)),
+                Some('#') if self.source[self.pos..].starts_with("#hash(") => {
+                    Some(self.scan_prefix("#hash(", Token::HashParen))
+                }
+                Some('#') if self.source[self.pos..].starts_with("#(") => {
+                    Some(self.scan_prefix("#(", Token::VectorParen))
+                }
                 Some('+') | Some('-') => Some(self.scan_number()),
-                Some(c) if c.is_digit(10) => Some(self.scan_number()),
+                Some(c) if c.is_ascii_digit() => Some(self.scan_number()),
                 Some('"') => Some(self.scan_string()),
+                Some('|') => Some(self.scan_bar_symbol()),
                 Some(_) if self.looking_at_symbol() => Some(self.scan_symbol()),
-                _ => {
-                    panic!("Invalid character");
+                Some(c) => {
+                    let start = self.pos;
+                    self.next_char();
+
+                    Some(Token::Invalid(&self.source[start..start + c.len_utf8()]))
                 }
             };
         }
@@ -280,6 +315,30 @@ mod tests {
         assert_eq!(lex.next(), Some(Token::RParen));
     }
 
+    #[test]
+    fn scan_hash_paren() {
+        let mut lex = lexer("#hash((a . 1)) #hash");
+
+        assert_eq!(lex.next(), Some(Token::HashParen));
+        assert_eq!(lex.next(), Some(Token::LParen));
+        assert_eq!(lex.next(), Some(Token::Symbol("a")));
+        assert_eq!(lex.next(), Some(Token::Symbol(".")));
+        assert_eq!(lex.next(), Some(Token::Int("1")));
+        assert_eq!(lex.next(), Some(Token::RParen));
+        assert_eq!(lex.next(), Some(Token::RParen));
+        assert_eq!(lex.next(), Some(Token::Symbol("#hash")));
+    }
+
+    #[test]
+    fn scan_vector_paren() {
+        let mut lex = lexer("#(1 #t)");
+
+        assert_eq!(lex.next(), Some(Token::VectorParen));
+        assert_eq!(lex.next(), Some(Token::Int("1")));
+        assert_eq!(lex.next(), Some(Token::Symbol("#t")));
+        assert_eq!(lex.next(), Some(Token::RParen));
+    }
+
     #[test]
     fn scan_int() {
         let mut lex = lexer("123 456 +89 -0001");
@@ -342,4 +401,49 @@ mod tests {
         assert_eq!(lex.next(), Some(Token::StrLit("\\\"mem\\es")));
         assert_eq!(lex.next(), Some(Token::StrLit("\\\"\\\"")));
     }
+
+    #[test]
+    fn scan_signed_symbol() {
+        let mut lex = lexer("->list -x +.a -.5");
+
+        assert_eq!(lex.next(), Some(Token::Symbol("->list")));
+        assert_eq!(lex.next(), Some(Token::Symbol("-x")));
+        assert_eq!(lex.next(), Some(Token::Symbol("+.a")));
+        assert_eq!(lex.next(), Some(Token::Float("-.5")));
+    }
+
+    #[test]
+    fn scan_bar_symbol() {
+        let mut lex = lexer(r#"|hello world| || |a\|b|"#);
+
+        assert_eq!(lex.next(), Some(Token::BarSymbol("hello world")));
+        assert_eq!(lex.next(), Some(Token::BarSymbol("")));
+        assert_eq!(lex.next(), Some(Token::BarSymbol("a\\|b")));
+    }
+
+    #[test]
+    fn scan_str_lit_trailing_backslash() {
+        let mut lex = lexer(r#" "a\\" b"#);
+
+        assert_eq!(lex.next(), Some(Token::StrLit("a\\\\")));
+        assert_eq!(lex.next(), Some(Token::Symbol("b")));
+    }
+
+    #[test]
+    fn skip_shebang() {
+        let mut lex = lexer("#!/usr/bin/env rslisp\n(x)");
+
+        assert_eq!(lex.next(), Some(Token::LParen));
+        assert_eq!(lex.next(), Some(Token::Symbol("x")));
+        assert_eq!(lex.next(), Some(Token::RParen));
+    }
+
+    #[test]
+    fn scan_unterminated() {
+        let mut lex = lexer(r#"a "open \" |b"#);
+
+        assert_eq!(lex.next(), Some(Token::Symbol("a")));
+        assert_eq!(lex.next(), Some(Token::Unterminated(r#""open \" |b"#)));
+        assert_eq!(lex.next(), None);
+    }
 }
@@ -1,7 +1,39 @@
-#![feature(box_patterns, const_fn)]
+// The `#[builtin]` attribute refers to this crate by name, so builtins
+// defined in here can use it too.
+extern crate self as rslisp;
 
 pub mod builtins;
+pub mod compile;
+pub mod convert;
+pub mod editor;
+pub mod error;
 pub mod eval;
+pub mod format;
+pub mod gc;
+pub mod hash;
+pub mod interpreter;
 pub mod lexer;
+pub mod load;
+pub mod module;
+pub mod native;
 pub mod parser;
+pub mod printer;
+pub mod record;
+pub mod registry;
+pub mod repl;
+pub mod resolve;
+pub mod string;
+pub mod symbol;
 pub mod types;
+pub mod vector;
+pub mod vm;
+
+pub use convert::{FromLisp, IntoLisp, Rest};
+pub use error::Error;
+pub use interpreter::{Backend, Interpreter, Value};
+pub use native::{Handle, Native, NativeMethods};
+pub use record::LispRecord;
+pub use rslisp_derive::{builtin, LispRecord};
+
+#[doc(hidden)]
+pub use inventory;
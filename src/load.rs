@@ -0,0 +1,280 @@
+//! Loading code from files: `load` evaluates a file by path, and `require`
+//! finds a module on the search path and loads it at most once.
+//!
+//! The search path is the global `*load-path*`, a list of directories. It
+//! starts out as the directories in `RSLISP_PATH` followed by the current
+//! directory, and programs can redefine it.
+
+use crate::error::Error;
+use crate::eval::eval_str;
+use crate::symbol::Symbol;
+use crate::types::{Scope, Type};
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// The global holding the search path.
+pub const LOAD_PATH: &str = "*load-path*";
+
+/// The extension of module files.
+pub const EXTENSION: &str = "lisp";
+
+/// Which modules an environment has required.
+///
+/// The environment of a module shares the requires and module definitions in
+/// progress with the environment it's defined in, so a cycle that runs
+/// through module environments is still caught.
+#[derive(Debug, Default, PartialEq)]
+pub struct Requires {
+    done: HashSet<Symbol>,
+    /// Modules being loaded, outermost first.
+    pending: Rc<RefCell<Vec<Symbol>>>,
+    /// Modules being defined, outermost first.
+    defining: Rc<RefCell<Vec<Symbol>>>,
+}
+
+impl Requires {
+    /// The requires of a module defined in this environment.
+    pub fn for_module(&self) -> Requires {
+        Requires {
+            done: HashSet::new(),
+            pending: self.pending.clone(),
+            defining: self.defining.clone(),
+        }
+    }
+
+    /// Note that `module` is being defined, until `end_module`.
+    pub fn begin_module(&self, module: Symbol) {
+        self.defining.borrow_mut().push(module);
+    }
+
+    pub fn end_module(&self) {
+        self.defining.borrow_mut().pop();
+    }
+
+    /// Fail if importing `module` would need the module being defined.
+    pub fn check_import(&self, module: Symbol) -> Result<(), Error> {
+        check_cycle("import", &self.defining.borrow(), module)
+    }
+}
+
+/// Fail if `module` is already on `stack`, naming the cycle.
+fn check_cycle(what: &str, stack: &[Symbol], module: Symbol) -> Result<(), Error> {
+    match stack.iter().position(|&name| name == module) {
+        Some(start) => {
+            let cycle: Vec<&str> = stack[start..]
+                .iter()
+                .chain(Some(&module))
+                .map(|name| name.as_str())
+                .collect();
+
+            Err(Error::Message(format!(
+                "cyclic {}: {}",
+                what,
+                cycle.join(" -> ")
+            )))
+        }
+        None => Ok(()),
+    }
+}
+
+/// The initial value of `*load-path*`.
+pub fn default_load_path() -> Type {
+    let mut dirs: Vec<Type> = match env::var_os("RSLISP_PATH") {
+        Some(paths) => env::split_paths(&paths)
+            .map(|dir| Type::StrLit(dir.to_string_lossy().into()))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    dirs.push(Type::StrLit(".".into()));
+
+    Type::ConsList(dirs.into_iter().collect())
+}
+
+/// Evaluate the file at `path` in `scope`, returning its last value.
+pub fn load(path: &Path, scope: &mut Scope) -> Result<Type, Error> {
+    eval_str(&read(path)?, scope)
+}
+
+/// The contents of the source file at `path`.
+pub fn read(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path)
+        .map_err(|err| Error::Message(format!("cannot read {}: {}", path.display(), err)))
+}
+
+/// Load `module` into `scope`'s globals, unless it already has been.
+pub fn require(module: Symbol, scope: &mut Scope) -> Result<(), Error> {
+    {
+        let mut globals = scope.globals().borrow_mut();
+        let requires = globals.requires();
+
+        if requires.done.contains(&module) {
+            return Ok(());
+        }
+
+        check_cycle("require", &requires.pending.borrow(), module)?;
+        requires.pending.borrow_mut().push(module);
+    }
+
+    // Modules are loaded at top level, whoever requires them.
+    let mut top = scope.top();
+    let result = find(module, scope).and_then(|path| load(&path, &mut top));
+
+    let mut globals = scope.globals().borrow_mut();
+    let requires = globals.requires();
+
+    requires.pending.borrow_mut().pop();
+
+    if result.is_ok() {
+        requires.done.insert(module);
+    }
+
+    result.map(|_| ())
+}
+
+/// The file for `module` in the first directory on the search path that has
+/// one.
+fn find(module: Symbol, scope: &Scope) -> Result<PathBuf, Error> {
+    let file = format!("{}.{}", module, EXTENSION);
+
+    let dirs = match scope.get(Symbol::intern(LOAD_PATH))? {
+        Type::ConsList(dirs) => dirs,
+        Type::Nil => Default::default(),
+        t => {
+            return Err(Error::WrongType {
+                name: LOAD_PATH.into(),
+                expected: "a list of directories",
+                got: t,
+            })
+        }
+    };
+
+    for dir in dirs.iter() {
+        let dir = match dir {
+            Type::StrLit(dir) => dir,
+            t => {
+                return Err(Error::WrongType {
+                    name: LOAD_PATH.into(),
+                    expected: "a list of directories",
+                    got: t.clone(),
+                })
+            }
+        };
+
+        let path = Path::new(&**dir).join(&file);
+
+        if path.is_file() {
+            return Ok(path);
+        }
+    }
+
+    Err(Error::Message(format!(
+        "cannot find module {} on {} {:#}",
+        module,
+        LOAD_PATH,
+        Type::ConsList(dirs)
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::eval::create_env;
+
+    /// A scratch directory holding `files`, removed when dropped.
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn new(name: &str, files: &[(&str, &str)]) -> Dir {
+            let dir = env::temp_dir().join(format!("rslisp-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+
+            for (file, source) in files {
+                fs::write(dir.join(file), source).unwrap();
+            }
+
+            Dir(dir)
+        }
+
+        fn env(&self) -> Scope {
+            let mut env = create_env();
+            let path = Type::StrLit(self.0.to_string_lossy().into());
+            env.put(
+                LOAD_PATH.into(),
+                Type::ConsList(vec![path].into_iter().collect()),
+            );
+            env
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn load_file() {
+        let dir = Dir::new("load", &[("double.lisp", "(define (double x) (* 2 x))")]);
+        let mut env = dir.env();
+
+        let code = format!("(load {:?}) (double 4)", dir.0.join("double.lisp"));
+        assert_eq!(eval_str(&code, &mut env), Ok(Type::Int(8)));
+    }
+
+    #[test]
+    fn require_once() {
+        let dir = Dir::new("require", &[("counter.lisp", "(define count (+ count 1))")]);
+        let mut env = dir.env();
+
+        let code = "(define count 0) (require 'counter) (require 'counter) count";
+        assert_eq!(eval_str(code, &mut env), Ok(Type::Int(1)));
+
+        assert!(eval_str("(require 'missing)", &mut env).is_err());
+    }
+
+    #[test]
+    fn require_cycle() {
+        let dir = Dir::new(
+            "cycle",
+            &[
+                ("a.lisp", "(require 'b)"),
+                ("b.lisp", "(require 'c)"),
+                ("c.lisp", "(require 'a)"),
+            ],
+        );
+        let mut env = dir.env();
+
+        assert_eq!(
+            eval_str("(require 'a)", &mut env),
+            Err(Error::Message("cyclic require: a -> b -> c -> a".into()))
+        );
+    }
+
+    #[test]
+    fn import_cycle() {
+        let dir = Dir::new(
+            "import-cycle",
+            &[
+                ("a.lisp", "(module a (export x) (import b) (define x 1))"),
+                ("b.lisp", "(module b (export y) (import a) (define y 2))"),
+            ],
+        );
+        let mut env = dir.env();
+
+        assert_eq!(
+            eval_str("(import a)", &mut env),
+            Err(Error::Message("cyclic import: a -> b -> a".into()))
+        );
+
+        // Nothing is left in progress, so the modules can still be fixed.
+        fs::write(dir.0.join("b.lisp"), "(module b (export y) (define y 2))").unwrap();
+        assert_eq!(eval_str("(import a) x", &mut env), Ok(Type::Int(1)));
+    }
+}
@@ -0,0 +1,248 @@
+//! Modules: namespaces with explicit exports.
+//!
+//! `(module name (export a b) body...)` evaluates `body` in an environment of
+//! its own and registers the module with the environment it appears in.
+//! `(import spec...)` then binds the exported values in the importer:
+//!
+//! - `name` imports every export of `name`.
+//! - `(only spec a b)` imports just `a` and `b`.
+//! - `(prefix spec p:)` imports each name with `p:` in front.
+//!
+//! A module sees the modules defined before it, but nothing else of the
+//! environment around it. Importing a module that hasn't been defined
+//! `require`s it first, so a library can live in a file of the same name.
+//! Modules whose files import each other fail with a `cyclic import` error.
+
+use crate::error::Error;
+use crate::eval::{create_env, eval_top_level};
+use crate::load::{self, LOAD_PATH};
+use crate::symbol::Symbol;
+use crate::types::{Scope, Type};
+
+use im::Vector;
+
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+pub struct Module {
+    name: Symbol,
+    env: Scope,
+    exports: Vec<Symbol>,
+}
+
+impl Module {
+    pub fn name(&self) -> Symbol {
+        self.name
+    }
+
+    pub fn env(&self) -> &Scope {
+        &self.env
+    }
+
+    pub fn exports(&self) -> &[Symbol] {
+        &self.exports
+    }
+
+    /// The exported bindings, in the order they were exported.
+    fn bindings(&self) -> Result<Vec<(Symbol, Type)>, Error> {
+        self.exports
+            .iter()
+            .map(|&name| Ok((name, self.env.get(name)?)))
+            .collect()
+    }
+}
+
+/// Evaluate a `(module name (export ...) body...)` form, given everything
+/// after `module`, and register the result in `scope`'s globals.
+pub fn define(args: Vector<Type>, scope: &mut Scope) -> Result<(), Error> {
+    let name = match args.get(0) {
+        Some(Type::Symbol(name)) => *name,
+        _ => return Err(Error::Syntax("module needs a name".into())),
+    };
+
+    let exports = match args.get(1) {
+        Some(Type::SExpr(export)) if export.head() == Some(&Type::Symbol("export".into())) => {
+            export
+                .iter()
+                .skip(1)
+                .map(Type::as_key)
+                .collect::<Result<Vec<Symbol>, Error>>()?
+        }
+        _ => {
+            let msg = format!("module {} needs an (export ...) list", name);
+            return Err(Error::Syntax(msg));
+        }
+    };
+
+    let mut env = create_env();
+
+    {
+        let mut outer = scope.globals().borrow_mut();
+        let mut inner = env.globals().borrow_mut();
+
+        for module in outer.modules() {
+            inner.add_module(module.clone());
+        }
+
+        *inner.requires() = outer.requires().for_module();
+    }
+
+    env.put(LOAD_PATH.into(), scope.get(LOAD_PATH.into())?);
+
+    scope.globals().borrow_mut().requires().begin_module(name);
+    let result = args
+        .skip(2)
+        .into_iter()
+        .try_for_each(|expr| eval_top_level(expr, &mut env).map(|_| ()));
+    scope.globals().borrow_mut().requires().end_module();
+
+    result?;
+
+    for &export in &exports {
+        if env.globals().borrow().lookup(export).is_none() {
+            return Err(Error::Message(format!(
+                "module {} exports {}, which it doesn't define",
+                name, export
+            )));
+        }
+    }
+
+    let module = Module { name, env, exports };
+    scope.globals().borrow_mut().add_module(Rc::new(module));
+
+    Ok(())
+}
+
+/// Bind everything named by an import `spec` in `scope`.
+pub fn import(spec: &Type, scope: &mut Scope) -> Result<(), Error> {
+    for (name, value) in import_set(spec, scope)? {
+        scope.put(name, value);
+    }
+
+    Ok(())
+}
+
+fn import_set(spec: &Type, scope: &mut Scope) -> Result<Vec<(Symbol, Type)>, Error> {
+    let sexpr = match spec {
+        Type::Symbol(name) => return find(*name, scope)?.bindings(),
+        Type::SExpr(sexpr) => sexpr,
+        _ => return Err(invalid(spec)),
+    };
+
+    match sexpr.head() {
+        Some(Type::Symbol(form)) if form.as_str() == "only" && sexpr.len() > 1 => {
+            let mut bindings = import_set(&sexpr[1], scope)?;
+            let names = sexpr
+                .iter()
+                .skip(2)
+                .map(Type::as_key)
+                .collect::<Result<Vec<Symbol>, Error>>()?;
+
+            for name in &names {
+                if !bindings.iter().any(|(bound, _)| bound == name) {
+                    let msg = format!("{:#} doesn't export {}", sexpr[1], name);
+                    return Err(Error::Message(msg));
+                }
+            }
+
+            bindings.retain(|(name, _)| names.contains(name));
+            Ok(bindings)
+        }
+        Some(Type::Symbol(form)) if form.as_str() == "prefix" && sexpr.len() == 3 => {
+            let prefix = sexpr[2].as_key()?;
+            let bindings = import_set(&sexpr[1], scope)?;
+
+            Ok(bindings
+                .into_iter()
+                .map(|(name, value)| (format!("{}{}", prefix, name).as_str().into(), value))
+                .collect())
+        }
+        _ => Err(invalid(spec)),
+    }
+}
+
+/// The module called `name`, requiring it first if it isn't defined yet.
+fn find(name: Symbol, scope: &mut Scope) -> Result<Rc<Module>, Error> {
+    if let Some(module) = scope.globals().borrow().module(name) {
+        return Ok(module);
+    }
+
+    scope.globals().borrow_mut().requires().check_import(name)?;
+    load::require(name, scope)?;
+
+    match scope.globals().borrow().module(name) {
+        Some(module) => Ok(module),
+        None => Err(Error::Message(format!("no module named {}", name))),
+    }
+}
+
+fn invalid(spec: &Type) -> Error {
+    Error::Syntax(format!("invalid import {:#}", spec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::eval::eval_str;
+    use crate::eval::testing::run_after;
+
+    const SHAPES: &str = "
+        (module shapes (export area perimeter)
+            (define (square x) (* x x))
+            (define (area side) (square side))
+            (define (perimeter side) (* 4 side)))";
+
+    /// `code` run after the definitions in `SHAPES`.
+    fn with_shapes(code: &str) -> Result<Type, Error> {
+        run_after(SHAPES, code)
+    }
+
+    #[test]
+    fn import_exports_only() {
+        assert_eq!(with_shapes("(import shapes) (area 3)"), Ok(Type::Int(9)));
+        assert_eq!(
+            with_shapes("(import shapes) (square 3)"),
+            Err(Error::Unbound("square".into()))
+        );
+        assert_eq!(with_shapes("(area 3)"), Err(Error::Unbound("area".into())));
+    }
+
+    #[test]
+    fn import_prefix_and_only() {
+        assert_eq!(
+            with_shapes("(import (prefix shapes s:)) (s:perimeter 2)"),
+            Ok(Type::Int(8))
+        );
+        assert_eq!(
+            with_shapes("(import (only shapes area)) perimeter"),
+            Err(Error::Unbound("perimeter".into()))
+        );
+        assert_eq!(
+            with_shapes("(import (prefix (only shapes area) s:)) (s:area 2)"),
+            Ok(Type::Int(4))
+        );
+        assert!(with_shapes("(import (only shapes square))").is_err());
+    }
+
+    #[test]
+    fn modules_keep_separate_namespaces() {
+        let code = "
+            (define (square x) 0)
+            (module uses-shapes (export ten)
+                (import shapes)
+                (define ten (+ (area 3) 1)))
+            (import uses-shapes)
+            (+ ten (square 5))";
+
+        assert_eq!(with_shapes(code), Ok(Type::Int(10)));
+    }
+
+    #[test]
+    fn missing_export() {
+        let mut env = create_env();
+
+        assert!(eval_str("(module broken (export nope))", &mut env).is_err());
+        assert!(eval_str("(import nowhere)", &mut env).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! Native objects: opaque host values, such as connections or buffers, that
+//! scripts can hold and pass around but not look into.
+//!
+//! A `Native` keeps its value behind an `Rc<dyn Any>`, so builtins get it
+//! back with a checked downcast. A value that implements `NativeMethods`
+//! can also be called as `(obj 'method args...)`.
+//!
+//! The collector doesn't see into natives, so a native that holds Lisp
+//! values can leak a cycle through them.
+
+use crate::convert::{FromLisp, IntoLisp};
+use crate::error::Error;
+use crate::symbol::Symbol;
+use crate::types::{Scope, Type};
+
+use im::Vector;
+
+use std::any::{self, Any};
+use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
+
+/// Methods callable from Lisp as `(obj 'method args...)`.
+pub trait NativeMethods: Any {
+    fn call_method(
+        &self,
+        method: Symbol,
+        args: Vector<Type>,
+        scope: &mut Scope,
+    ) -> Result<Type, Error>;
+}
+
+type Dispatch = fn(&dyn Any, Symbol, Vector<Type>, &mut Scope) -> Result<Type, Error>;
+
+fn dispatch<T: NativeMethods>(
+    value: &dyn Any,
+    method: Symbol,
+    args: Vector<Type>,
+    scope: &mut Scope,
+) -> Result<Type, Error> {
+    value
+        .downcast_ref::<T>()
+        .expect("native dispatch for the wrong type")
+        .call_method(method, args, scope)
+}
+
+#[derive(Clone)]
+pub struct Native {
+    type_name: Rc<str>,
+    value: Rc<dyn Any>,
+    dispatch: Option<Dispatch>,
+}
+
+impl Native {
+    /// Wrap `value`, named after its type without the module path.
+    pub fn new<T: Any>(value: T) -> Native {
+        Native {
+            type_name: short_type_name(any::type_name::<T>()).into(),
+            value: Rc::new(value),
+            dispatch: None,
+        }
+    }
+
+    /// Wrap `value`, whose methods scripts can call.
+    pub fn with_methods<T: NativeMethods>(value: T) -> Native {
+        Native {
+            dispatch: Some(dispatch::<T>),
+            ..Native::new(value)
+        }
+    }
+
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    pub fn is<T: Any>(&self) -> bool {
+        self.value.is::<T>()
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+
+    /// A shared reference to the value, if it's a `T`.
+    pub fn downcast<T: Any>(&self) -> Option<Rc<T>> {
+        self.value.clone().downcast().ok()
+    }
+
+    /// Call `(self method args...)`, given everything after `self`.
+    pub fn call(&self, args: Vector<Type>, scope: &mut Scope) -> Result<Type, Error> {
+        let dispatch = match self.dispatch {
+            Some(dispatch) => dispatch,
+            None => return Err(Error::NotCallable(Type::Native(self.clone()))),
+        };
+
+        match args.head() {
+            Some(Type::Symbol(method)) => dispatch(&*self.value, *method, args.skip(1), scope),
+            _ => Err(Error::Message(format!(
+                "#<native {}>: expected a method name",
+                self.type_name
+            ))),
+        }
+    }
+}
+
+/// `alloc::vec::Vec<alloc::string::String>` as `Vec<String>`.
+fn short_type_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    let mut start = 0;
+
+    for (i, ch) in name.char_indices() {
+        if ch.is_alphanumeric() || ch == '_' || ch == ':' {
+            continue;
+        }
+
+        short.push_str(name[start..i].rsplit("::").next().unwrap());
+        short.push(ch);
+        start = i + ch.len_utf8();
+    }
+
+    short.push_str(name[start..].rsplit("::").next().unwrap());
+    short
+}
+
+/// Natives are equal only if they're the same object.
+impl PartialEq for Native {
+    fn eq(&self, other: &Native) -> bool {
+        Rc::ptr_eq(&self.value, &other.value)
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Native `{}`", self.type_name)
+    }
+}
+
+impl FromLisp for Native {
+    fn from_lisp(value: Type) -> Result<Native, Error> {
+        match value {
+            Type::Native(native) => Ok(native),
+            got => Err(Error::Conversion {
+                expected: "a native object",
+                got,
+            }),
+        }
+    }
+}
+
+impl IntoLisp for Native {
+    fn into_lisp(self) -> Result<Type, Error> {
+        Ok(Type::Native(self))
+    }
+}
+
+/// A typed native argument: converts from a native holding a `T`.
+#[derive(Debug)]
+pub struct Handle<T>(pub Rc<T>);
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        Handle(self.0.clone())
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Any> FromLisp for Handle<T> {
+    fn from_lisp(value: Type) -> Result<Handle<T>, Error> {
+        if let Type::Native(native) = &value {
+            if let Some(rc) = native.downcast() {
+                return Ok(Handle(rc));
+            }
+        }
+
+        Err(Error::Conversion {
+            expected: "a native object of the right type",
+            got: value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::eval::{create_env, eval_str};
+
+    use std::cell::RefCell;
+
+    struct Counter(RefCell<i64>);
+
+    impl NativeMethods for Counter {
+        fn call_method(
+            &self,
+            method: Symbol,
+            args: Vector<Type>,
+            _scope: &mut Scope,
+        ) -> Result<Type, Error> {
+            match method.as_str() {
+                "add!" => {
+                    for arg in args {
+                        *self.0.borrow_mut() += i64::from_lisp(arg)?;
+                    }
+                    Ok(Type::Nil)
+                }
+                "get" => Ok(Type::Int(*self.0.borrow())),
+                _ => Err(Error::Message(format!("Counter has no method {}", method))),
+            }
+        }
+    }
+
+    #[test]
+    fn type_names() {
+        assert_eq!(short_type_name("my_app::db::Connection"), "Connection");
+        assert_eq!(
+            short_type_name("alloc::vec::Vec<alloc::string::String>"),
+            "Vec<String>"
+        );
+        assert_eq!(
+            Type::Native(Native::new(vec![1u8])).to_string(),
+            "#<native Vec<u8>>"
+        );
+    }
+
+    #[test]
+    fn downcast() {
+        let native = Native::new(String::from("buffer"));
+
+        assert!(native.is::<String>());
+        assert_eq!(native.downcast_ref::<String>().unwrap(), "buffer");
+        assert!(native.downcast::<i64>().is_none());
+
+        let value = Type::Native(native.clone());
+        assert_eq!(Handle::<String>::from_lisp(value.clone()).unwrap().len(), 6);
+        assert!(Handle::<i64>::from_lisp(value).is_err());
+        assert_ne!(native, Native::new(String::from("buffer")));
+    }
+
+    #[test]
+    fn call_methods() {
+        let mut env = create_env();
+        let counter = Native::with_methods(Counter(RefCell::new(0)));
+        env.put("counter".into(), Type::Native(counter.clone()));
+        env.put("plain".into(), Type::Native(Native::new(0u8)));
+
+        assert_eq!(
+            eval_str("(counter 'add! 2 3) (counter 'get)", &mut env),
+            Ok(Type::Int(5))
+        );
+        assert_eq!(*counter.downcast_ref::<Counter>().unwrap().0.borrow(), 5);
+        assert_eq!(
+            eval_str("(counter 'reset!)", &mut env),
+            Err(Error::Message("Counter has no method reset!".into()))
+        );
+        assert!(eval_str("(counter 1)", &mut env).is_err());
+        assert!(matches!(
+            eval_str("(plain 'get)", &mut env),
+            Err(Error::NotCallable(Type::Native(_)))
+        ));
+    }
+}
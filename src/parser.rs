@@ -1,45 +1,95 @@
+use crate::error::Error;
+use crate::eval::quote;
+use crate::hash::HashTable;
 use crate::lexer::{Lexer, Token};
 use crate::types::Type;
+use crate::vector::VectorCell;
 use im::Vector;
 
 use std::collections::LinkedList;
 use std::iter::FromIterator;
+use std::rc::Rc;
 
 #[derive(Debug, PartialEq)]
 enum ValOrCtrl {
     LParen,
+    HashParen,
+    VectorParen,
     RParen,
     Quote,
+    /// The `.` of a pair `(x . y)`.
+    Dot,
     Val(Type),
 }
 
-/// Pop an sexpression from the top of the stack
-fn pop_sexpr(stack: &mut LinkedList<ValOrCtrl>) -> Type {
+/// Pop an sexpression, or the literal its opener starts, from the top of the
+/// stack
+fn pop_sexpr(stack: &mut LinkedList<ValOrCtrl>) -> Result<Type, Error> {
     stack.pop_back();
     let mut tokens: LinkedList<Type> = LinkedList::new();
+    let mut dots = Vec::new();
 
-    let mut top = stack.back();
-
-    while let Some(ValOrCtrl::Val(val)) = top {
-        tokens.push_front(val.clone());
+    loop {
+        match stack.back() {
+            Some(ValOrCtrl::Val(val)) => tokens.push_front(val.clone()),
+            Some(ValOrCtrl::Dot) => dots.push(tokens.len()),
+            _ => break,
+        }
 
         stack.pop_back();
-        top = stack.back();
     }
 
-    if stack.is_empty() {
-        panic!("Too many right parens");
+    let opener = match stack.pop_back() {
+        Some(opener) => opener,
+        None => panic!("Too many right parens"),
+    };
+
+    // Only `(x . y)` has a dot, one datum after it and one before.
+    match (&opener, dots.as_slice(), tokens.len()) {
+        (_, [], _) => {}
+        (ValOrCtrl::LParen, [1], 2) => {
+            let pair = tokens.into_iter().map(quote).collect();
+            return Ok(Type::Cons(pair));
+        }
+        _ => {
+            return Err(Error::Syntax(
+                "expected one datum on each side of `.`".into(),
+            ))
+        }
     }
 
-    stack.pop_back();
+    match opener {
+        ValOrCtrl::HashParen => return hash_literal(tokens),
+        ValOrCtrl::VectorParen => {
+            let elems = tokens.into_iter().map(quote).collect();
+            return Ok(Type::Vector(VectorCell::literal(elems)));
+        }
+        _ => {}
+    }
 
-    if tokens.len() == 0 {
-        return Type::Nil;
+    if tokens.is_empty() {
+        return Ok(Type::Nil);
     }
 
-    let list = Vector::from_iter(tokens.into_iter());
+    let list = Vector::from_iter(tokens);
 
-    Type::SExpr(list)
+    Ok(Type::SExpr(list))
+}
+
+/// The table read from the entries of `#hash((key . value) ...)`.
+fn hash_literal(entries: LinkedList<Type>) -> Result<Type, Error> {
+    let entries = entries
+        .into_iter()
+        .map(|entry| match &entry {
+            Type::Cons(pair) => Ok((pair[0].clone(), pair[1].clone())),
+            _ => Err(Error::Syntax(format!(
+                "expected a (key . value) entry, got {:#}",
+                entry
+            ))),
+        })
+        .collect::<Result<Vec<(Type, Type)>, Error>>()?;
+
+    Ok(Type::HashMap(HashTable::literal(entries)))
 }
 
 /// Handle any number of quotes
@@ -53,7 +103,7 @@ fn handle_quotes(stack: &mut LinkedList<ValOrCtrl>, sexpr: Type) -> Type {
     let mut top = stack.back();
 
     while let Some(ValOrCtrl::Quote) = top {
-        sexpr = Type::Quoted(Box::new(sexpr));
+        sexpr = Type::Quoted(Rc::new(sexpr));
 
         stack.pop_back();
         top = stack.back();
@@ -62,7 +112,12 @@ fn handle_quotes(stack: &mut LinkedList<ValOrCtrl>, sexpr: Type) -> Type {
     sexpr
 }
 
-pub fn parse<'a>(lex: &'a mut Lexer<'a>) -> impl Iterator<Item = Type> + 'a {
+/// Parse every expression in `lex`.
+///
+/// Each item is an expression or the error that stopped one. An expression
+/// still open when the source runs out gives `Error::Incomplete`, so callers
+/// reading interactively can wait for more input.
+pub fn parse<'a>(lex: &'a mut Lexer<'a>) -> impl Iterator<Item = Result<Type, Error>> + 'a {
     let mut stack: LinkedList<ValOrCtrl> = LinkedList::new();
     let mut paren_count = 0;
 
@@ -73,48 +128,103 @@ pub fn parse<'a>(lex: &'a mut Lexer<'a>) -> impl Iterator<Item = Type> + 'a {
                     paren_count += 1;
                     stack.push_back(ValOrCtrl::LParen);
                 }
+                Token::HashParen => {
+                    paren_count += 1;
+                    stack.push_back(ValOrCtrl::HashParen);
+                }
+                Token::VectorParen => {
+                    paren_count += 1;
+                    stack.push_back(ValOrCtrl::VectorParen);
+                }
                 Token::Quote => stack.push_back(ValOrCtrl::Quote),
+                Token::Symbol(".") => {
+                    if let None | Some(ValOrCtrl::Quote) = stack.back() {
+                        paren_count = 0;
+                        stack.clear();
+                        return Some(Err(Error::Syntax("unexpected `.`".into())));
+                    }
+
+                    stack.push_back(ValOrCtrl::Dot);
+                }
                 Token::RParen => {
                     paren_count -= 1;
 
                     if paren_count < 0 {
-                        panic!("too many closing parens");
+                        paren_count = 0;
+                        stack.clear();
+                        return Some(Err(Error::Syntax("unexpected `)`".into())));
                     }
 
                     if let Some(ValOrCtrl::Quote) = stack.back() {
-                        panic!("invalid quotation");
+                        paren_count = 0;
+                        stack.clear();
+                        return Some(Err(Error::Syntax("nothing to quote before `)`".into())));
                     }
 
                     stack.push_back(ValOrCtrl::RParen);
 
-                    let mut new_sexpr = pop_sexpr(&mut stack);
+                    let mut new_sexpr = match pop_sexpr(&mut stack) {
+                        Ok(sexpr) => sexpr,
+                        Err(err) => {
+                            paren_count = 0;
+                            stack.clear();
+                            return Some(Err(err));
+                        }
+                    };
 
                     if stack.is_empty() {
-                        return Some(new_sexpr);
+                        return Some(Ok(new_sexpr));
                     }
 
                     new_sexpr = handle_quotes(&mut stack, new_sexpr);
 
                     if stack.is_empty() {
-                        return Some(new_sexpr);
+                        return Some(Ok(new_sexpr));
                     }
 
                     stack.push_back(ValOrCtrl::Val(new_sexpr));
                 }
                 Token::Comment(_) => {}
+                Token::Unterminated(_) => {
+                    stack.clear();
+                    return Some(Err(Error::Incomplete));
+                }
+                Token::Invalid(text) => {
+                    paren_count = 0;
+                    stack.clear();
+                    return Some(Err(Error::Syntax(format!("unexpected `{}`", text))));
+                }
                 _ => {
-                    let mut val = Type::from_tok(tok);
+                    let mut val = match Type::from_tok(tok) {
+                        Ok(val) => val,
+                        Err(err) => {
+                            paren_count = 0;
+                            stack.clear();
+                            return Some(Err(err));
+                        }
+                    };
                     val = handle_quotes(&mut stack, val);
 
                     if stack.is_empty() {
-                        return Some(val);
+                        return Some(Ok(val));
                     }
 
                     stack.push_back(ValOrCtrl::Val(val));
                 }
             }
-        } else {
+        } else if stack.is_empty() {
             return None;
+        } else {
+            stack.clear();
+            return Some(Err(Error::Incomplete));
         }
     })
 }
+
+/// Parse all of `source`, stopping at the first error.
+pub fn read(source: &str) -> Result<Vec<Type>, Error> {
+    let mut lexer = Lexer::new(source);
+    let exprs = parse(&mut lexer).collect();
+
+    exprs
+}
@@ -0,0 +1,332 @@
+//! Lisp syntax printing for `Type`.
+//!
+//! `{}` gives the `display` form, meant for people: strings print their
+//! contents as is. `{:#}` gives the `write` form, which escapes strings so
+//! the output reads back as the same value.
+//!
+//! A record, vector or hash table that contains itself prints the inner
+//! occurrence as a placeholder, such as `#<point ...>`, instead of looping.
+
+use crate::error::Error;
+use crate::gc;
+use crate::lexer::Lexer;
+use crate::types::Type;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        print(self, f, f.alternate())
+    }
+}
+
+/// The `write` form of `value`.
+pub fn write(value: &Type) -> String {
+    format!("{:#}", value)
+}
+
+/// The `display` form of `value`.
+pub fn display(value: &Type) -> String {
+    format!("{}", value)
+}
+
+fn print(value: &Type, f: &mut fmt::Formatter, readable: bool) -> fmt::Result {
+    match value {
+        Type::Int(int) => write!(f, "{}", int),
+        Type::Float(flo) => print_float(*flo, f),
+        Type::Bool(true) => f.write_str("#t"),
+        Type::Bool(false) => f.write_str("#f"),
+        Type::StrLit(string) if readable => print_escaped(string, '"', f),
+        Type::StrLit(string) => f.write_str(string),
+        Type::Symbol(sym) if readable && needs_bars(sym.as_str()) => {
+            print_escaped(sym.as_str(), '|', f)
+        }
+        Type::Symbol(sym) => write!(f, "{}", sym),
+        Type::Cons(pair) => {
+            f.write_str("(")?;
+            print(&pair[0], f, readable)?;
+            f.write_str(" . ")?;
+            print(&pair[1], f, readable)?;
+            f.write_str(")")
+        }
+        Type::ConsList(list) | Type::SExpr(list) => {
+            f.write_str("(")?;
+
+            for (i, elem) in list.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(" ")?;
+                }
+
+                print(elem, f, readable)?;
+            }
+
+            f.write_str(")")
+        }
+        Type::Quoted(quoted) => {
+            f.write_str("'")?;
+            print(quoted, f, readable)
+        }
+        Type::Function(fun) => match fun.name() {
+            Some(name) => write!(f, "#<procedure {}>", name),
+            None => f.write_str("#<procedure>"),
+        },
+        Type::Builtin(builtin) => write!(f, "#<procedure {}>", builtin.name()),
+        Type::Macro(mac) => write!(f, "#<macro {}>", mac.name()),
+        Type::Vector(vector) => gc::visit(&**vector, || {
+            f.write_str("#(")?;
+
+            for (i, elem) in vector.elems().iter().enumerate() {
+                if i > 0 {
+                    f.write_str(" ")?;
+                }
+
+                print(elem, f, readable)?;
+            }
+
+            f.write_str(")")
+        })
+        .unwrap_or_else(|| f.write_str("#(...)")),
+        Type::HashMap(table) => gc::visit(&**table, || {
+            f.write_str("#hash(")?;
+
+            for (i, (key, value)) in table.entries().into_iter().enumerate() {
+                if i > 0 {
+                    f.write_str(" ")?;
+                }
+
+                f.write_str("(")?;
+                print(&key, f, readable)?;
+                f.write_str(" . ")?;
+                print(&value, f, readable)?;
+                f.write_str(")")?;
+            }
+
+            f.write_str(")")
+        })
+        .unwrap_or_else(|| f.write_str("#hash(...)")),
+        Type::Native(native) => write!(f, "#<native {}>", native.type_name()),
+        Type::Record(record) => {
+            let record_type = record.record_type();
+
+            gc::visit(&**record, || {
+                write!(f, "#<{}", record_type.name())?;
+
+                for (field, value) in record_type.fields().iter().zip(record.values()) {
+                    write!(f, " {}: ", field)?;
+                    print(&value, f, readable)?;
+                }
+
+                f.write_str(">")
+            })
+            .unwrap_or_else(|| write!(f, "#<{} ...>", record_type.name()))
+        }
+        Type::Lambda(lambda) => match lambda.name() {
+            Some(name) => write!(f, "#<lambda {}>", name),
+            None => f.write_str("#<lambda>"),
+        },
+        Type::Local(depth, index) => write!(f, "#<local {} {}>", depth, index),
+        Type::Global(slot) => write!(f, "#<global {}>", slot),
+        Type::Nil => f.write_str("()"),
+    }
+}
+
+/// Floats always print with a fractional part, so they read back as floats.
+fn print_float(flo: f64, f: &mut fmt::Formatter) -> fmt::Result {
+    let text = flo.to_string();
+
+    if text.contains('.') || !flo.is_finite() {
+        f.write_str(&text)
+    } else {
+        write!(f, "{}.0", text)
+    }
+}
+
+/// Whether the lexer would read `name` as anything other than that symbol.
+fn needs_bars(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    let first = match chars.next() {
+        Some(first) => first,
+        None => return true,
+    };
+
+    let looks_numeric = match first {
+        '+' | '-' => {
+            let rest = chars.as_str();
+            rest.starts_with(|c: char| c.is_ascii_digit())
+                || (rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        }
+        c => c.is_numeric(),
+    };
+
+    looks_numeric
+        || first == '#'
+        || name == "."
+        || name
+            .chars()
+            .any(|c| c == '|' || c.is_whitespace() || !Lexer::allowed_in_symbol(&c))
+}
+
+/// Print `text` between `delim`s, escaped so the lexer reads it back as is.
+fn print_escaped(text: &str, delim: char, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", delim)?;
+
+    for ch in text.chars() {
+        match ch {
+            c if c == delim => write!(f, "\\{}", delim)?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\r' => f.write_str("\\r")?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+
+    write!(f, "{}", delim)
+}
+
+thread_local! {
+    static OUTPUT: RefCell<Box<dyn Write>> = RefCell::new(Box::new(io::stdout()));
+}
+
+/// Replace the current output port, returning the previous one.
+pub fn set_output(output: Box<dyn Write>) -> Box<dyn Write> {
+    OUTPUT.with(|out| std::mem::replace(&mut *out.borrow_mut(), output))
+}
+
+/// Write `text` to the current output port. Failing to write, say because
+/// the reader of a pipe went away, is an error like any other.
+pub fn output(text: &str) -> Result<(), Error> {
+    OUTPUT.with(|out| {
+        let mut out = out.borrow_mut();
+
+        out.write_all(text.as_bytes())
+            .and_then(|_| out.flush())
+            .map_err(|err| Error::Message(format!("cannot write output: {}", err)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::eval::{create_env, eval};
+    use crate::parser::parse;
+    use crate::symbol::Symbol;
+
+    use std::rc::Rc;
+
+    fn read(code: &str) -> Type {
+        let mut lexer = Lexer::new(code);
+        let mut exprs = parse(&mut lexer);
+
+        exprs.next().unwrap().unwrap()
+    }
+
+    #[test]
+    fn write_and_display() {
+        let value = read(r#"(1 2.0 "a \"b\"\n" sym 'quoted ())"#);
+
+        assert_eq!(write(&value), r#"(1 2.0 "a \"b\"\n" sym 'quoted ())"#);
+        assert_eq!(display(&value), "(1 2.0 a \"b\"\n sym 'quoted ())");
+
+        assert_eq!(write(&read("(#t #false)")), "(#t #f)");
+    }
+
+    #[test]
+    fn write_symbols_with_bars() {
+        let symbols = ["hello world", "", "123", "-5", "+.5", "a|b", "#t", "(paren"];
+
+        for name in symbols.iter() {
+            let sym = Type::Symbol(Symbol::intern(name));
+
+            assert_eq!(read(&write(&sym)), sym);
+            assert_eq!(display(&sym), *name);
+        }
+
+        let plain = Type::Symbol(Symbol::intern("->list"));
+        assert_eq!(write(&plain), "->list");
+    }
+
+    #[test]
+    fn print_procedures() {
+        let mut lexer = Lexer::new("(define (add-new x y) (+ x y)) add-new +");
+        let mut env = create_env();
+        let values: Vec<Type> = parse(&mut lexer)
+            .map(|e| eval(e.unwrap(), &mut env).unwrap())
+            .collect();
+
+        assert_eq!(display(&values[1]), "#<procedure add-new>");
+        assert_eq!(display(&values[2]), "#<procedure +>");
+    }
+
+    #[derive(Clone, Default)]
+    struct Capture(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn output_builtins() {
+        let capture = Capture::default();
+        let stdout = set_output(Box::new(capture.clone()));
+
+        let code = r#"(display "hi") (newline) (write "hi") (print 1 "two" 'three)
+                      (printf "~a=~3d~%" 'x 5)"#;
+        let mut lexer = Lexer::new(code);
+        let mut env = create_env();
+
+        for expr in parse(&mut lexer) {
+            eval(expr.unwrap(), &mut env).unwrap();
+        }
+
+        set_output(stdout);
+
+        let text = String::from_utf8(capture.0.borrow().clone()).unwrap();
+        assert_eq!(text, "hi\n\"hi\"1 two three\nx=  5\n");
+    }
+
+    struct Closed;
+
+    impl Write for Closed {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn closed_output_is_an_error() {
+        let stdout = set_output(Box::new(Closed));
+
+        let mut lexer = Lexer::new("(display 1)");
+        let expr = parse(&mut lexer).next().unwrap().unwrap();
+        let result = eval(expr, &mut create_env());
+
+        set_output(stdout);
+
+        assert!(
+            matches!(result, Err(Error::Message(msg)) if msg.starts_with("cannot write output"))
+        );
+    }
+
+    #[test]
+    fn newline_takes_no_arguments() {
+        let mut lexer = Lexer::new("(newline 5)");
+        let expr = parse(&mut lexer).next().unwrap().unwrap();
+
+        assert!(eval(expr, &mut create_env()).is_err());
+    }
+}
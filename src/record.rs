@@ -0,0 +1,423 @@
+//! Records: values with a fixed set of named, mutable fields.
+//!
+//! Each record points at its `RecordType`, the descriptor naming the type
+//! and its fields. Two records are of the same type only if they share a
+//! descriptor, so two types that happen to have the same name and fields
+//! are still told apart.
+//!
+//! Scripts define record types with R7RS `define-record-type`:
+//!
+//! ```scheme
+//! (define-record-type <point>
+//!   (make-point x y)
+//!   point?
+//!   (x point-x set-point-x!)
+//!   (y point-y))
+//! ```
+//!
+//! Rust types get one with `#[derive(LispRecord)]`.
+
+use crate::builtins::expect_args;
+use crate::error::Error;
+use crate::gc::{self, Trace, Tracer};
+use crate::symbol::Symbol;
+use crate::types::{BuiltinFunction, Scope, Type};
+
+use im::Vector;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// The descriptor of a record type.
+#[derive(Debug)]
+pub struct RecordType {
+    name: Symbol,
+    fields: Vec<Symbol>,
+}
+
+impl RecordType {
+    pub fn new(name: &str, fields: &[&str]) -> RecordType {
+        RecordType {
+            name: Symbol::intern(name),
+            fields: fields.iter().map(|field| Symbol::intern(field)).collect(),
+        }
+    }
+
+    pub fn name(&self) -> Symbol {
+        self.name
+    }
+
+    pub fn fields(&self) -> &[Symbol] {
+        &self.fields
+    }
+
+    /// The position of the field called `name`.
+    pub fn field_index(&self, name: Symbol) -> Option<usize> {
+        self.fields.iter().position(|&field| field == name)
+    }
+}
+
+pub struct Record {
+    record_type: Rc<RecordType>,
+    fields: RefCell<Vec<Type>>,
+}
+
+impl Record {
+    /// A tracked record of type `record_type`, with one value per field.
+    pub fn new(record_type: Rc<RecordType>, fields: Vec<Type>) -> Rc<Record> {
+        assert_eq!(record_type.fields.len(), fields.len());
+
+        gc::alloc(Record {
+            record_type,
+            fields: RefCell::new(fields),
+        })
+    }
+
+    pub fn record_type(&self) -> &Rc<RecordType> {
+        &self.record_type
+    }
+
+    /// Whether the record's type is `record_type`.
+    pub fn is(&self, record_type: &Rc<RecordType>) -> bool {
+        Rc::ptr_eq(&self.record_type, record_type)
+    }
+
+    pub fn get(&self, index: usize) -> Type {
+        self.fields.borrow()[index].clone()
+    }
+
+    pub fn set(&self, index: usize, value: Type) {
+        self.fields.borrow_mut()[index] = value;
+    }
+
+    /// The field values, in the order of the type's fields.
+    pub fn values(&self) -> Vec<Type> {
+        self.fields.borrow().clone()
+    }
+}
+
+/// Records are equal if they're of the same type and their fields are.
+impl PartialEq for Record {
+    fn eq(&self, other: &Record) -> bool {
+        gc::equal(self, other, || {
+            self.is(&other.record_type) && *self.fields.borrow() == *other.fields.borrow()
+        })
+    }
+}
+
+impl fmt::Debug for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Record `{}` {:?}",
+            self.record_type.name,
+            self.fields.borrow()
+        )
+    }
+}
+
+impl Trace for Record {
+    fn trace(&self, tracer: &mut Tracer) {
+        for value in self.fields.borrow().iter() {
+            tracer.value(value);
+        }
+    }
+
+    fn clear(&self) {
+        for value in self.fields.borrow_mut().iter_mut() {
+            *value = Type::Nil;
+        }
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of::<Record>() + self.fields.borrow().len() * std::mem::size_of::<Type>()
+    }
+}
+
+/// A Rust type with a record type of its own, usually through
+/// `#[derive(LispRecord)]`.
+pub trait LispRecord {
+    /// The descriptor shared by every record made from this type.
+    fn record_type() -> Rc<RecordType>;
+
+    /// The constructor, predicate, accessors and modifiers of the type.
+    fn builtins() -> Vec<BuiltinFunction>;
+}
+
+/// `value` as a record of type `record_type`, for the builtin `name`.
+///
+/// Record types can be defined at runtime, so the error is a `Message`
+/// naming the type rather than a `WrongType`.
+pub fn instance<'a>(
+    name: &str,
+    record_type: &Rc<RecordType>,
+    value: &'a Type,
+) -> Result<&'a Rc<Record>, Error> {
+    match value {
+        Type::Record(record) if record.is(record_type) => Ok(record),
+        got => Err(Error::Message(format!(
+            "{}: expected a {}, got {:#}",
+            name,
+            record_type.name(),
+            got
+        ))),
+    }
+}
+
+/// Evaluate a `define-record-type` form, given everything after
+/// `define-record-type`, binding its procedures in `scope`.
+///
+/// The type name may be written `<point>` or `point`; records print with the
+/// brackets left off. Fields the constructor doesn't take start out as `()`.
+pub fn define(args: Vector<Type>, scope: &mut Scope) -> Result<(), Error> {
+    let syntax = |msg: &str| Error::Syntax(format!("define-record-type: {}", msg));
+
+    if args.len() < 3 {
+        return Err(syntax("expected a type name, constructor and predicate"));
+    }
+
+    let type_name = args[0].as_key()?;
+    let name = type_name.as_str();
+    let name = name
+        .strip_prefix('<')
+        .and_then(|name| name.strip_suffix('>'))
+        .unwrap_or(name);
+
+    let specs = args
+        .iter()
+        .skip(3)
+        .map(|spec| match spec {
+            Type::SExpr(spec) if !spec.is_empty() && spec.len() <= 3 => spec
+                .iter()
+                .map(Type::as_key)
+                .collect::<Result<Vec<Symbol>, Error>>(),
+            _ => Err(syntax(&format!("invalid field {:#}", spec))),
+        })
+        .collect::<Result<Vec<Vec<Symbol>>, Error>>()?;
+
+    let fields: Vec<&str> = specs.iter().map(|spec| spec[0].as_str()).collect();
+    let record_type = Rc::new(RecordType::new(name, &fields));
+
+    let mut bindings = Vec::new();
+
+    match &args[1] {
+        Type::SExpr(constructor) if !constructor.is_empty() => {
+            let make = constructor[0].as_key()?;
+            let params = constructor
+                .iter()
+                .skip(1)
+                .map(|param| {
+                    let param = param.as_key()?;
+                    record_type
+                        .field_index(param)
+                        .ok_or_else(|| syntax(&format!("{} isn't a field", param)))
+                })
+                .collect::<Result<Vec<usize>, Error>>()?;
+
+            bindings.push((make, constructor_fn(make, &record_type, params)));
+        }
+        Type::Symbol(_) | Type::Bool(false) => {}
+        constructor => return Err(syntax(&format!("invalid constructor {:#}", constructor))),
+    }
+
+    let predicate = args[2].as_key()?;
+    bindings.push((predicate, predicate_fn(predicate, &record_type)));
+
+    for (index, spec) in specs.iter().enumerate() {
+        if let Some(&accessor) = spec.get(1) {
+            bindings.push((accessor, accessor_fn(accessor, &record_type, index)));
+        }
+
+        if let Some(&modifier) = spec.get(2) {
+            bindings.push((modifier, modifier_fn(modifier, &record_type, index)));
+        }
+    }
+
+    for (name, builtin) in bindings {
+        scope.put(name, Type::Builtin(Rc::new(builtin)));
+    }
+
+    Ok(())
+}
+
+fn constructor_fn(
+    name: Symbol,
+    record_type: &Rc<RecordType>,
+    params: Vec<usize>,
+) -> BuiltinFunction {
+    let record_type = record_type.clone();
+
+    BuiltinFunction::new(name.as_str().into(), move |args, _scope| {
+        expect_args(name.as_str(), &args, params.len())?;
+
+        let mut fields = vec![Type::Nil; record_type.fields().len()];
+
+        for (&index, value) in params.iter().zip(args) {
+            fields[index] = value;
+        }
+
+        Ok(Type::Record(Record::new(record_type.clone(), fields)))
+    })
+}
+
+fn predicate_fn(name: Symbol, record_type: &Rc<RecordType>) -> BuiltinFunction {
+    let record_type = record_type.clone();
+
+    BuiltinFunction::new(name.as_str().into(), move |args, _scope| {
+        expect_args(name.as_str(), &args, 1)?;
+
+        Ok(Type::Bool(
+            matches!(&args[0], Type::Record(record) if record.is(&record_type)),
+        ))
+    })
+}
+
+fn accessor_fn(name: Symbol, record_type: &Rc<RecordType>, index: usize) -> BuiltinFunction {
+    let record_type = record_type.clone();
+
+    BuiltinFunction::new(name.as_str().into(), move |args, _scope| {
+        expect_args(name.as_str(), &args, 1)?;
+
+        Ok(instance(name.as_str(), &record_type, &args[0])?.get(index))
+    })
+}
+
+fn modifier_fn(name: Symbol, record_type: &Rc<RecordType>, index: usize) -> BuiltinFunction {
+    let record_type = record_type.clone();
+
+    BuiltinFunction::new(name.as_str().into(), move |args, _scope| {
+        expect_args(name.as_str(), &args, 2)?;
+
+        instance(name.as_str(), &record_type, &args[0])?.set(index, args[1].clone());
+        Ok(Type::Nil)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point() -> Rc<RecordType> {
+        Rc::new(RecordType::new("point", &["x", "y"]))
+    }
+
+    #[test]
+    fn equal_by_type_and_fields() {
+        let point_type = point();
+        let a = Record::new(point_type.clone(), vec![Type::Int(1), Type::Int(2)]);
+        let b = Record::new(point_type, vec![Type::Int(1), Type::Int(2)]);
+        let other = Record::new(point(), vec![Type::Int(1), Type::Int(2)]);
+
+        assert_eq!(a, b);
+        assert_ne!(a, other);
+
+        b.set(0, Type::Int(5));
+        assert_ne!(a, b);
+        assert_eq!(b.get(0), Type::Int(5));
+    }
+
+    const POINT: &str = "
+        (define-record-type <point>
+          (make-point x y)
+          point?
+          (x point-x set-point-x!)
+          (y point-y))";
+
+    /// `code` run after the definitions in `POINT`.
+    fn with_point(code: &str) -> Result<Type, Error> {
+        crate::eval::testing::run_after(POINT, code)
+    }
+
+    #[test]
+    fn define_record_type() {
+        assert_eq!(
+            with_point("(define p (make-point 1 2)) (set-point-x! p 5) (point-x p)"),
+            Ok(Type::Int(5))
+        );
+        assert_eq!(with_point("(point-y (make-point 1 2))"), Ok(Type::Int(2)));
+        assert_eq!(
+            with_point("(point? (make-point 1 2))"),
+            Ok(Type::Bool(true))
+        );
+        assert_eq!(with_point("(point? 5)"), Ok(Type::Bool(false)));
+        assert_eq!(
+            with_point("(make-point 1 \"two\")").map(|p| format!("{:#}", p)),
+            Ok("#<point x: 1 y: \"two\">".into())
+        );
+    }
+
+    #[test]
+    fn record_errors() {
+        let message = |code: &str| with_point(code).unwrap_err().to_string();
+
+        assert_eq!(
+            with_point("(set-point-x! 5 1)"),
+            instance("set-point-x!", &point(), &Type::Int(5)).map(|_| Type::Nil)
+        );
+        assert_eq!(message("(point-x 5)"), "point-x: expected a point, got 5");
+        assert_eq!(
+            message("(make-point 1)"),
+            "make-point: expected 2 arguments, got 1"
+        );
+        assert!(with_point("(define-record-type bad (make-bad z) bad? (x bad-x))").is_err());
+        assert!(with_point("(define-record-type bad (make-bad) bad? 5)").is_err());
+    }
+
+    #[test]
+    fn equal_records() {
+        assert_eq!(
+            with_point("(equal? (make-point 1 '(2)) (make-point 1 '(2)))"),
+            Ok(Type::Bool(true))
+        );
+        assert_eq!(
+            with_point("(equal? (make-point 1 2) (make-point 1 3))"),
+            Ok(Type::Bool(false))
+        );
+    }
+
+    #[test]
+    fn cyclic_records() {
+        let code = "
+            (define p (make-point 1 2))
+            (set-point-x! p p)
+            (define q (make-point 1 2))
+            (set-point-x! q q)
+            (define r (make-point 1 3))
+            (set-point-x! r r)";
+        let mut env = crate::eval::create_env();
+        crate::eval::eval_str(POINT, &mut env).unwrap();
+        crate::eval::eval_str(code, &mut env).unwrap();
+
+        let mut eval = |code: &str| crate::eval::eval_str(code, &mut env).unwrap();
+
+        assert_eq!(eval("(equal? p p)"), Type::Bool(true));
+        assert_eq!(eval("(equal? p q)"), Type::Bool(true));
+        assert_eq!(format!("{:#}", eval("p")), "#<point x: #<point ...> y: 2>");
+        assert_eq!(eval("(equal? p r)"), Type::Bool(false));
+    }
+
+    #[test]
+    fn partial_constructor() {
+        let code = "
+            (define-record-type node (make-node value) node? (value node-value) (next node-next set-node-next!))
+            (define n (make-node 1))
+            (node-next n)";
+
+        assert_eq!(with_point(code), Ok(Type::Nil));
+    }
+
+    #[test]
+    fn collect_record_cycle() {
+        gc::collect();
+        let before = gc::stats().live;
+
+        let record = Record::new(point(), vec![Type::Nil, Type::Nil]);
+        record.set(0, Type::Record(record.clone()));
+        drop(record);
+
+        assert!(gc::stats().live > before);
+        gc::collect();
+        assert_eq!(gc::stats().live, before);
+    }
+}
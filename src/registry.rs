@@ -0,0 +1,115 @@
+//! The registry of builtins.
+//!
+//! `#[builtin]` submits every builtin it defines here, in this crate or any
+//! other linked into the program, and `create_env` binds them all. A plugin
+//! crate only has to define its builtins; nothing has to list them.
+
+use crate::symbol::Symbol;
+use crate::types::{BuiltinFunction, BuiltinMacro, Type};
+
+use std::rc::Rc;
+
+enum Build {
+    Function(fn() -> BuiltinFunction),
+    Functions(fn() -> Vec<BuiltinFunction>),
+    Macro(fn() -> BuiltinMacro),
+}
+
+pub struct Registration {
+    module: &'static str,
+    build: Build,
+}
+
+impl Registration {
+    pub const fn for_function(
+        module: &'static str,
+        build: fn() -> BuiltinFunction,
+    ) -> Registration {
+        Registration {
+            module,
+            build: Build::Function(build),
+        }
+    }
+
+    /// A group of builtins built together, such as those of a record type.
+    pub const fn for_functions(
+        module: &'static str,
+        build: fn() -> Vec<BuiltinFunction>,
+    ) -> Registration {
+        Registration {
+            module,
+            build: Build::Functions(build),
+        }
+    }
+
+    pub const fn for_macro(module: &'static str, build: fn() -> BuiltinMacro) -> Registration {
+        Registration {
+            module,
+            build: Build::Macro(build),
+        }
+    }
+
+    /// The path of the module the builtin was defined in.
+    pub fn module(&self) -> &'static str {
+        self.module
+    }
+
+    /// Build the builtin, bound to its name and each of its aliases.
+    fn bindings(&self) -> Vec<(Symbol, Type)> {
+        match self.build {
+            Build::Function(build) => function(build()),
+            Build::Functions(build) => build().into_iter().flat_map(function).collect(),
+            Build::Macro(build) => {
+                let mac = build();
+                let names = names(mac.name(), mac.aliases());
+                bind(names, Type::Macro(Rc::new(mac)))
+            }
+        }
+    }
+}
+
+fn function(builtin: BuiltinFunction) -> Vec<(Symbol, Type)> {
+    let names = names(builtin.name(), builtin.aliases());
+    bind(names, Type::Builtin(Rc::new(builtin)))
+}
+
+fn names(name: &str, aliases: &[String]) -> Vec<Symbol> {
+    std::iter::once(name)
+        .chain(aliases.iter().map(String::as_str))
+        .map(Symbol::intern)
+        .collect()
+}
+
+fn bind(names: Vec<Symbol>, value: Type) -> Vec<(Symbol, Type)> {
+    names
+        .into_iter()
+        .map(|name| (name, value.clone()))
+        .collect()
+}
+
+inventory::collect!(Registration);
+
+/// Every registered builtin, bound to its name and each of its aliases.
+///
+/// Builtins are bound in order of the module that defined them, so when two
+/// share a name the same one wins every time.
+pub fn bindings() -> impl Iterator<Item = (Symbol, Type)> {
+    let mut registrations: Vec<&Registration> = inventory::iter::<Registration>().collect();
+    registrations.sort_by_key(|registration| registration.module);
+
+    registrations.into_iter().flat_map(Registration::bindings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_builtins_are_registered() {
+        let names: Vec<Symbol> = bindings().map(|(name, _)| name).collect();
+
+        for name in &["define", "+", "add", "gc-stats", "import", "doc"] {
+            assert!(names.contains(&Symbol::intern(name)), "{} missing", name);
+        }
+    }
+}
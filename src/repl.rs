@@ -0,0 +1,197 @@
+//! The read-eval-print loop behind the `main` binary.
+//!
+//! `Repl` does no I/O itself: it's fed one line at a time and answers with
+//! what to print, so the terminal handling stays in the binary.
+
+use crate::error::Error;
+use crate::interpreter::{Backend, Interpreter};
+use crate::parser;
+use crate::types::Scope;
+
+/// What the REPL wants done after a line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    /// The input so far is an unfinished expression; read another line.
+    More,
+    /// Print each of these lines.
+    Output(Vec<String>),
+    /// End the session with this exit status.
+    Quit(i32),
+}
+
+pub struct Repl {
+    interp: Interpreter,
+    /// Lines of an expression that isn't finished yet.
+    buffer: String,
+}
+
+impl Default for Repl {
+    fn default() -> Repl {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl::with_backend(Backend::default())
+    }
+
+    /// A REPL that runs code on `backend`.
+    pub fn with_backend(backend: Backend) -> Repl {
+        Repl {
+            interp: Interpreter::with_backend(backend),
+            buffer: String::new(),
+        }
+    }
+
+    pub fn env(&self) -> &Scope {
+        self.interp.env()
+    }
+
+    /// The prompt for the next line.
+    pub fn prompt(&self) -> &'static str {
+        if self.buffer.is_empty() {
+            "> "
+        } else {
+            "... "
+        }
+    }
+
+    /// Drop any unfinished expression, as after Ctrl-C.
+    pub fn cancel(&mut self) {
+        self.buffer.clear();
+    }
+
+    pub fn feed(&mut self, line: &str) -> Reply {
+        if self.buffer.is_empty() && line.trim_start().starts_with(':') {
+            return self.command(line.trim());
+        }
+
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+
+        let exprs = match parser::read(&self.buffer) {
+            Err(Error::Incomplete) => return Reply::More,
+            result => {
+                self.buffer.clear();
+                result
+            }
+        };
+
+        let exprs = match exprs {
+            Ok(exprs) => exprs,
+            Err(err) => return Reply::Output(vec![format!("error: {}", err)]),
+        };
+
+        let mut output = Vec::new();
+
+        for expr in exprs {
+            match self.interp.eval(expr) {
+                Ok(value) => output.push(format!("{:#}", value)),
+                Err(Error::Exit(code)) => return Reply::Quit(code),
+                Err(err) => {
+                    output.push(format!("error: {}", err));
+                    break;
+                }
+            }
+        }
+
+        Reply::Output(output)
+    }
+
+    fn command(&mut self, command: &str) -> Reply {
+        match command {
+            ":quit" | ":q" => Reply::Quit(0),
+            ":env" => {
+                let globals = self.interp.env().globals().borrow();
+
+                let mut bindings: Vec<String> = globals
+                    .bindings()
+                    .map(|(name, value)| format!("{} = {:#}", name, value))
+                    .collect();
+                bindings.sort();
+
+                Reply::Output(bindings)
+            }
+            ":reset" => {
+                self.interp = Interpreter::with_backend(self.interp.backend());
+                Reply::Output(vec!["environment reset".into()])
+            }
+            _ => Reply::Output(vec![format!(
+                "unknown command `{}`, expected :quit, :env or :reset",
+                command
+            )]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn keep_scope_across_inputs() {
+        let mut repl = Repl::new();
+
+        assert_eq!(repl.feed("(define x 2)"), Reply::Output(output(&["()"])));
+        assert_eq!(
+            repl.feed("(+ x 1) \"str\""),
+            Reply::Output(output(&["3", "\"str\""]))
+        );
+    }
+
+    #[test]
+    fn wait_for_balanced_parens() {
+        let mut repl = Repl::new();
+
+        assert_eq!(repl.feed("(define (add-new x y)"), Reply::More);
+        assert_eq!(repl.prompt(), "... ");
+        assert_eq!(repl.feed("  (+ x y))"), Reply::Output(output(&["()"])));
+        assert_eq!(repl.prompt(), "> ");
+        assert_eq!(repl.feed("(add-new 1 2)"), Reply::Output(output(&["3"])));
+
+        assert_eq!(repl.feed("(add-new"), Reply::More);
+        repl.cancel();
+        assert_eq!(repl.prompt(), "> ");
+    }
+
+    #[test]
+    fn errors_keep_session() {
+        let mut repl = Repl::new();
+
+        assert_eq!(
+            repl.feed("(+ 1 nope)"),
+            Reply::Output(output(&["error: unbound variable: nope"]))
+        );
+        assert_eq!(
+            repl.feed(")"),
+            Reply::Output(output(&["error: syntax error: unexpected `)`"]))
+        );
+        assert_eq!(repl.feed("(+ 1 2)"), Reply::Output(output(&["3"])));
+        assert_eq!(repl.feed("(exit 2)"), Reply::Quit(2));
+    }
+
+    #[test]
+    fn meta_commands() {
+        let mut repl = Repl::new();
+
+        repl.feed("(define answer 42)");
+
+        match repl.feed(":env") {
+            Reply::Output(lines) => assert!(lines.contains(&"answer = 42".to_string())),
+            reply => panic!("expected output, got {:?}", reply),
+        }
+
+        repl.feed(":reset");
+        assert_eq!(
+            repl.feed("answer"),
+            Reply::Output(output(&["error: unbound variable: answer"]))
+        );
+
+        assert_eq!(repl.feed(":quit"), Reply::Quit(0));
+    }
+}
@@ -0,0 +1,309 @@
+use crate::error::Error;
+use crate::symbol::Symbol;
+use crate::types::{Frame, Globals, Lambda, Scope, Type};
+
+use im::Vector;
+
+use std::rc::Rc;
+
+/// Resolve `expr` to be evaluated directly in `scope`, as a top-level form
+/// is.
+pub fn resolve_in(expr: Type, scope: &Scope) -> Result<Type, Error> {
+    let mut globals = scope.globals().borrow_mut();
+
+    Resolver::new(&mut globals, scope.frame()).expr(expr)
+}
+
+/// Resolves the variables in function bodies to frame slots.
+///
+/// A reference to a parameter or internal define becomes a
+/// `Type::Local(depth, index)`, where `depth` counts the frames between the
+/// reference and the function that owns the variable. Every other symbol
+/// becomes a `Type::Global` slot, allocated unbound if it hasn't been
+/// defined yet so that forward references keep working.
+pub struct Resolver<'g> {
+    globals: &'g mut Globals,
+    /// The names of each enclosing frame, outermost first.
+    frames: Vec<Rc<[Symbol]>>,
+}
+
+impl<'g> Resolver<'g> {
+    /// A resolver for code that will run inside `frame`.
+    pub fn new(globals: &'g mut Globals, frame: Option<&Rc<Frame>>) -> Resolver<'g> {
+        let mut frames = Vec::new();
+        let mut frame = frame;
+
+        while let Some(current) = frame {
+            frames.push(current.names().clone());
+            frame = current.parent();
+        }
+
+        frames.reverse();
+
+        Resolver { globals, frames }
+    }
+
+    pub fn lambda(
+        &mut self,
+        name: Option<Symbol>,
+        params: Vector<Type>,
+        body: Vector<Type>,
+    ) -> Result<Lambda, Error> {
+        let mut names = params
+            .iter()
+            .map(Type::as_key)
+            .collect::<Result<Vec<Symbol>, Error>>()?;
+        let arity = names.len();
+
+        for expr in body.iter() {
+            if let Some(defined) = internal_define(expr) {
+                if !names.contains(&defined) {
+                    names.push(defined);
+                }
+            }
+        }
+
+        let names: Rc<[Symbol]> = names.into();
+
+        self.frames.push(names.clone());
+        let body: Result<Vector<Type>, Error> = body.into_iter().map(|e| self.resolve(e)).collect();
+        self.frames.pop();
+
+        Ok(Lambda::new(name, arity, names, body?))
+    }
+
+    /// Resolve an expression evaluated directly in the resolver's frame.
+    pub fn expr(&mut self, expr: Type) -> Result<Type, Error> {
+        self.resolve(expr)
+    }
+
+    fn resolve(&mut self, expr: Type) -> Result<Type, Error> {
+        Ok(match expr {
+            Type::Symbol(sym) => self.variable(sym),
+            Type::SExpr(sexpr) => {
+                if self.is_define(&sexpr) {
+                    self.define(sexpr)?
+                } else if self.is_macro_call(&sexpr) {
+                    // Macros receive their arguments unevaluated, so leave them
+                    // as written; the expansion looks variables up by name.
+                    Type::SExpr(sexpr)
+                } else {
+                    let sexpr = sexpr.into_iter().map(|e| self.resolve(e));
+                    Type::SExpr(sexpr.collect::<Result<_, _>>()?)
+                }
+            }
+            _ => expr,
+        })
+    }
+
+    fn variable(&mut self, sym: Symbol) -> Type {
+        for (depth, names) in self.frames.iter().rev().enumerate() {
+            if let Some(index) = names.iter().position(|name| *name == sym) {
+                return Type::Local(depth, index);
+            }
+        }
+
+        Type::Global(self.globals.slot(sym))
+    }
+
+    /// Whether `sexpr` is a define, unless a local variable shadows it.
+    fn is_define(&mut self, sexpr: &Vector<Type>) -> bool {
+        match sexpr.head() {
+            Some(Type::Symbol(sym)) if sym.as_str() == "define" => {
+                matches!(self.variable(*sym), Type::Global(_))
+            }
+            _ => false,
+        }
+    }
+
+    fn is_macro_call(&mut self, sexpr: &Vector<Type>) -> bool {
+        match sexpr.head() {
+            Some(Type::Symbol(sym)) => {
+                matches!(self.variable(*sym), Type::Global(_))
+                    && matches!(self.globals.lookup(*sym), Some(Type::Macro(_)))
+            }
+            _ => false,
+        }
+    }
+
+    /// Resolve the target of a define to its slot in the current frame, and
+    /// a function definition to a `Type::Lambda`.
+    fn define(&mut self, sexpr: Vector<Type>) -> Result<Type, Error> {
+        let head = self.resolve(sexpr[0].clone())?;
+
+        let (target, value) = match sexpr.get(1) {
+            None => return Err(Error::Syntax("define needs a name".into())),
+            Some(Type::SExpr(name_and_params)) => {
+                let name = name_and_params[0].as_key()?;
+                let lambda = self.lambda(Some(name), name_and_params.skip(1), sexpr.skip(2))?;
+
+                (name, Type::Lambda(Rc::new(lambda)))
+            }
+            Some(target) => {
+                let value = define_value(target, &sexpr.skip(1))?;
+
+                (target.as_key()?, self.resolve(value)?)
+            }
+        };
+
+        let target = self.variable(target);
+
+        Ok(Type::SExpr(vec![head, target, value].into_iter().collect()))
+    }
+}
+
+/// The value in `(define target value)`, given the arguments after `define`.
+pub fn define_value(target: &Type, args: &Vector<Type>) -> Result<Type, Error> {
+    match args.len() {
+        1 => Err(Error::Syntax(format!(
+            "define needs a value for {:#}",
+            target
+        ))),
+        2 => Ok(args[1].clone()),
+        got => Err(Error::Arity {
+            name: "define".into(),
+            expected: 2,
+            got,
+        }),
+    }
+}
+
+/// The name bound by `expr`, if it's a define.
+fn internal_define(expr: &Type) -> Option<Symbol> {
+    let sexpr = match expr {
+        Type::SExpr(sexpr) if sexpr.len() > 1 => sexpr,
+        _ => return None,
+    };
+
+    match sexpr.head() {
+        Some(Type::Symbol(sym)) if sym.as_str() == "define" => {}
+        _ => return None,
+    }
+
+    match &sexpr[1] {
+        Type::Symbol(name) => Some(*name),
+        Type::SExpr(name_and_params) => name_and_params.head()?.as_key().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::lexer::Lexer;
+    use crate::parser::parse;
+
+    fn read(code: &str) -> Type {
+        let mut lexer = Lexer::new(code);
+        let mut exprs = parse(&mut lexer);
+
+        exprs.next().unwrap().unwrap()
+    }
+
+    fn sexpr(items: Vec<Type>) -> Type {
+        Type::SExpr(items.into_iter().collect())
+    }
+
+    #[test]
+    fn resolve_params_and_globals() {
+        let mut globals = Globals::default();
+        let plus = globals.slot(Symbol::intern("+"));
+
+        let params = match read("(x y)") {
+            Type::SExpr(params) => params,
+            _ => unreachable!(),
+        };
+        let body = Vector::unit(read("(+ y x)"));
+
+        let lambda = Resolver::new(&mut globals, None)
+            .lambda(None, params, body)
+            .unwrap();
+
+        assert_eq!(
+            lambda.body()[0],
+            sexpr(vec![
+                Type::Global(plus),
+                Type::Local(0, 1),
+                Type::Local(0, 0)
+            ])
+        );
+    }
+
+    #[test]
+    fn resolve_captured_variable_depth() {
+        let mut globals = Globals::default();
+        let plus = globals.slot(Symbol::intern("+"));
+        let define = globals.slot(Symbol::intern("define"));
+
+        let params = match read("(x)") {
+            Type::SExpr(params) => params,
+            _ => unreachable!(),
+        };
+        let body = Vector::unit(read("(define (inner y) (+ x y))"));
+
+        let lambda = Resolver::new(&mut globals, None)
+            .lambda(None, params, body)
+            .unwrap();
+
+        let inner = match &lambda.body()[0] {
+            Type::SExpr(sexpr) => {
+                assert_eq!(sexpr[0], Type::Global(define));
+                assert_eq!(sexpr[1], Type::Local(0, 1));
+
+                match &sexpr[2] {
+                    Type::Lambda(inner) => inner.clone(),
+                    t => panic!("expected a lambda, got {:?}", t),
+                }
+            }
+            t => panic!("expected a define, got {:?}", t),
+        };
+
+        assert_eq!(
+            inner.body()[0],
+            sexpr(vec![
+                Type::Global(plus),
+                Type::Local(1, 0),
+                Type::Local(0, 0)
+            ])
+        );
+    }
+
+    #[test]
+    fn resolve_top_level() {
+        let env = crate::eval::create_env();
+        let slot = |name| env.globals().borrow_mut().slot(Symbol::intern(name));
+        let (plus, x) = (slot("+"), slot("x"));
+
+        assert_eq!(
+            resolve_in(read("(+ x 1)"), &env),
+            Ok(sexpr(vec![
+                Type::Global(plus),
+                Type::Global(x),
+                Type::Int(1)
+            ]))
+        );
+    }
+
+    #[test]
+    fn define_arguments() {
+        let env = crate::eval::create_env();
+
+        assert_eq!(
+            resolve_in(read("(define x 1 2)"), &env),
+            Err(Error::Arity {
+                name: "define".into(),
+                expected: 2,
+                got: 3
+            })
+        );
+        assert_eq!(
+            resolve_in(read("(define x)"), &env),
+            Err(Error::Syntax("define needs a value for x".into()))
+        );
+        assert_eq!(
+            resolve_in(read("(define)"), &env),
+            Err(Error::Syntax("define needs a name".into()))
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Strings: the helpers behind the string builtins.
+//!
+//! Strings are indexed by chars, not bytes, so `(string-ref "héllo" 1)` is
+//! `"é"`. There's no character type; a single character is a string of
+//! length one.
+
+use crate::lexer::{Lexer, Token};
+use crate::types::Type;
+
+/// The number of chars in `string`.
+pub fn char_len(string: &str) -> usize {
+    string.chars().count()
+}
+
+/// The byte offset of the char at `index`, or of the end of the string if
+/// `index` is its length.
+pub fn byte_offset(string: &str, index: usize) -> Option<usize> {
+    string
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(string.len()))
+        .nth(index)
+}
+
+/// The chars of `string` from `start` up to, but not including, `end`.
+pub fn char_slice(string: &str, start: usize, end: usize) -> Option<&str> {
+    if start > end {
+        return None;
+    }
+
+    let from = byte_offset(string, start)?;
+    let to = from + byte_offset(&string[from..], end - start)?;
+
+    Some(&string[from..to])
+}
+
+/// The number `text` reads as, if it's a single integer or float.
+pub fn parse_number(text: &str) -> Option<Type> {
+    let mut lexer = Lexer::new(text);
+
+    let number = match lexer.next()? {
+        Token::Int(int) => Type::Int(int.parse().ok()?),
+        Token::Float(flo) => Type::Float(flo.parse().ok()?),
+        _ => return None,
+    };
+
+    match lexer.next() {
+        None => Some(number),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::eval::testing::written;
+
+    #[test]
+    fn slices() {
+        assert_eq!(char_slice("héllo", 1, 3), Some("él"));
+        assert_eq!(char_slice("héllo", 5, 5), Some(""));
+        assert_eq!(char_slice("héllo", 2, 6), None);
+        assert_eq!(char_slice("héllo", 3, 2), None);
+        assert_eq!(byte_offset("日本", 1), Some(3));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number(" -12 "), Some(Type::Int(-12)));
+        assert_eq!(parse_number("+.5"), Some(Type::Float(0.5)));
+        assert_eq!(parse_number("1 2"), None);
+        assert_eq!(parse_number("abc"), None);
+        assert_eq!(parse_number(""), None);
+    }
+
+    #[test]
+    fn builtins() {
+        assert_eq!(written("(string-length \"héllo\")"), Ok("5".into()));
+        assert_eq!(
+            written("(string-append \"a\" \"bc\" \"\")"),
+            Ok("\"abc\"".into())
+        );
+        assert_eq!(written("(substring \"héllo\" 1 3)"), Ok("\"él\"".into()));
+        assert_eq!(written("(substring \"héllo\" 2)"), Ok("\"llo\"".into()));
+        assert_eq!(written("(string-ref \"日本語\" 2)"), Ok("\"語\"".into()));
+        assert_eq!(
+            written("(string-upcase \"straße\")"),
+            Ok("\"STRASSE\"".into())
+        );
+        assert_eq!(written("(string-downcase \"ÀB\")"), Ok("\"àb\"".into()));
+        assert_eq!(
+            written("(string-split \"a,b,,c\" \",\")"),
+            Ok("(\"a\" \"b\" \"\" \"c\")".into())
+        );
+        assert_eq!(
+            written("(string-split \"  a  b \")"),
+            Ok("(\"a\" \"b\")".into())
+        );
+        assert_eq!(
+            written("(string-join '(\"a\" \"b\") \", \")"),
+            Ok("\"a, b\"".into())
+        );
+        assert_eq!(written("(string-join '())"), Ok("\"\"".into()));
+        assert_eq!(written("(string-trim \"\\t hi \\n\")"), Ok("\"hi\"".into()));
+        assert_eq!(
+            written("(string-contains \"héllo\" \"llo\")"),
+            Ok("2".into())
+        );
+        assert_eq!(
+            written("(string-contains \"hello\" \"x\")"),
+            Ok("#f".into())
+        );
+        assert_eq!(
+            written("(string-replace \"a-b-c\" \"-\" \"+\")"),
+            Ok("\"a+b+c\"".into())
+        );
+        assert_eq!(written("(string->list \"hé\")"), Ok("(\"h\" \"é\")".into()));
+        assert_eq!(written("(string->list \"\")"), Ok("()".into()));
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(written("(string->number \"42\")"), Ok("42".into()));
+        assert_eq!(written("(string->number \"4.5\")"), Ok("4.5".into()));
+        assert_eq!(written("(string->number \"x\")"), Ok("#f".into()));
+        assert_eq!(written("(number->string 1.0)"), Ok("\"1.0\"".into()));
+        assert_eq!(written("(string->symbol \"a b\")"), Ok("|a b|".into()));
+        assert_eq!(written("(symbol->string 'abc)"), Ok("\"abc\"".into()));
+        assert_eq!(
+            written("(format \"~a is ~s~%\" \"x\" \"y\")"),
+            Ok("\"x is \\\"y\\\"\\n\"".into())
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            written("(string-ref \"abc\" 3)"),
+            Err("string-ref: index 3 out of range for a string of length 3".into())
+        );
+        assert_eq!(
+            written("(substring \"abc\" 2 1)"),
+            Err("substring: range 2 to 1 out of range for a string of length 3".into())
+        );
+        assert_eq!(
+            written("(string-length 'abc)"),
+            Err("string-length: expected a string, got abc".into())
+        );
+        assert_eq!(
+            written("(number->string \"1\")"),
+            Err("number->string: expected a number, got \"1\"".into())
+        );
+    }
+}
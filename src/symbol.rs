@@ -0,0 +1,86 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+/// An interned symbol name.
+///
+/// Symbols are small copyable ids into a per-thread table, so comparing
+/// two symbols or hashing one never touches the underlying string.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&sym) = self.ids.get(name) {
+            return sym;
+        }
+
+        // Interned names live as long as the program, so leaking them lets
+        // `as_str` hand out `&'static str` without holding the table borrowed.
+        let name: &'static str = Box::leak(name.to_owned().into_boxed_str());
+        let sym = Symbol(self.names.len() as u32);
+
+        self.names.push(name);
+        self.ids.insert(name, sym);
+
+        sym
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| interner.borrow_mut().intern(name))
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.with(|interner| interner.borrow().names[self.0 as usize])
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_same_name() {
+        assert_eq!(Symbol::intern("add"), Symbol::intern("add"));
+        assert_ne!(Symbol::intern("add"), Symbol::intern("mul"));
+    }
+
+    #[test]
+    fn print_original_name() {
+        let sym = Symbol::intern("add-new");
+
+        assert_eq!(sym.as_str(), "add-new");
+        assert_eq!(format!("{}", sym), "add-new");
+        assert_eq!(format!("{:?}", sym), "\"add-new\"");
+    }
+}
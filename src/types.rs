@@ -1,5 +1,15 @@
+use crate::compile::Chunk;
+use crate::error::Error;
 use crate::eval::eval;
+use crate::gc::{self, Trace, Tracer};
+use crate::hash::HashTable;
 use crate::lexer::Token;
+use crate::load::Requires;
+use crate::module::Module;
+use crate::native::Native;
+use crate::record::Record;
+use crate::symbol::Symbol;
+use crate::vector::VectorCell;
 
 use im::Vector;
 
@@ -7,71 +17,137 @@ use std::cell::RefCell;
 use std::cmp::PartialEq;
 use std::collections::HashMap;
 use std::fmt;
-use std::ops::Index;
 use std::rc::Rc;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     Float(f64),
     Int(i64),
-    StrLit(String),
-    Symbol(String),
+    Bool(bool),
+    StrLit(Rc<str>),
+    Symbol(Symbol),
     Cons(Vector<Type>),
     ConsList(Vector<Type>),
     SExpr(Vector<Type>),
-    Quoted(Box<Type>),
-    Function(Function),
-    Builtin(Rc<RefCell<BuiltinFunction>>),
-    Macro(Rc<RefCell<BuiltinMacro>>),
+    Quoted(Rc<Type>),
+    Function(Rc<Function>),
+    Lambda(Rc<Lambda>),
+    Local(usize, usize),
+    Global(usize),
+    Builtin(Rc<BuiltinFunction>),
+    Macro(Rc<BuiltinMacro>),
+    Record(Rc<Record>),
+    HashMap(Rc<HashTable>),
+    Vector(Rc<VectorCell>),
+    Native(Native),
     Nil,
 }
 
 impl Type {
-    pub fn from_tok<'a>(token: Token<'a>) -> Type {
-        match token {
-            Token::Float(flo) => Type::Float(flo.parse().unwrap()),
-            Token::Int(int) => Type::Int(int.parse().unwrap()),
-            Token::StrLit(lit) => Type::StrLit(lit.into()),
-            Token::Symbol(sym) => Type::Symbol(sym.into()),
+    pub fn from_tok<'a>(token: Token<'a>) -> Result<Type, Error> {
+        let invalid = |text: &str| Error::Syntax(format!("invalid number `{}`", text));
+
+        Ok(match token {
+            Token::Float(flo) => Type::Float(flo.parse().map_err(|_| invalid(flo))?),
+            Token::Int(int) => Type::Int(int.parse().map_err(|_| invalid(int))?),
+            Token::StrLit(lit) => Type::StrLit(unescape(lit).into()),
+            Token::Symbol("#t") | Token::Symbol("#true") => Type::Bool(true),
+            Token::Symbol("#f") | Token::Symbol("#false") => Type::Bool(false),
+            Token::Symbol(sym) => Type::Symbol(Symbol::intern(sym)),
+            Token::BarSymbol(sym) => Type::Symbol(Symbol::intern(&unescape(sym))),
             _ => panic!("cannot convert from {:?} to a Type", token),
-        }
+        })
     }
 
-    pub fn as_key(&self) -> String {
+    pub fn as_key(&self) -> Result<Symbol, Error> {
         match self {
-            Type::Symbol(ref string) => string.clone(),
-            _ => panic!("only symbols are keys"),
+            Type::Symbol(sym) => Ok(*sym),
+            _ => Err(Error::Syntax(format!("expected a name, got {:#}", self))),
         }
     }
 }
 
+/// Replace the escape sequences in a string literal with the chars they
+/// stand for.
+fn unescape(lit: &str) -> String {
+    let mut string = String::with_capacity(lit.len());
+    let mut chars = lit.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            string.push(ch);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => string.push('\n'),
+            Some('t') => string.push('\t'),
+            Some('r') => string.push('\r'),
+            Some(escaped) => string.push(escaped),
+            None => {}
+        }
+    }
+
+    string
+}
+
 pub trait Call {
-    fn call(&self, args: Vector<Type>, stg: &mut Scope) -> Type;
+    fn call(&self, args: Vector<Type>, stg: &mut Scope) -> Result<Type, Error>;
 }
 
+/// The signature every builtin function and macro is called through.
+pub type BuiltinFn = dyn Fn(Vector<Type>, &mut Scope) -> Result<Type, Error>;
+
 pub struct BuiltinFunction {
     name: String,
-    inner: Box<dyn FnMut(Vector<Type>, &mut Scope) -> Type>,
+    aliases: Vec<String>,
+    doc: Option<String>,
+    inner: Box<BuiltinFn>,
 }
 
 impl BuiltinFunction {
     pub fn new(
         name: String,
-        fun: impl FnMut(Vector<Type>, &mut Scope) -> Type + 'static,
+        fun: impl Fn(Vector<Type>, &mut Scope) -> Result<Type, Error> + 'static,
     ) -> BuiltinFunction {
         BuiltinFunction {
             name,
+            aliases: Vec::new(),
+            doc: None,
             inner: Box::new(fun),
         }
     }
+
+    /// Also bind the builtin as `alias`.
+    pub fn with_alias(mut self, alias: &str) -> BuiltinFunction {
+        self.aliases.push(alias.into());
+        self
+    }
+
+    pub fn with_doc(mut self, doc: &str) -> BuiltinFunction {
+        self.doc = Some(doc.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    pub fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
 }
 
 pub trait BuiltinCall {
-    fn call_builtin(&mut self, args: Vector<Type>, scope: &mut Scope) -> Type;
+    fn call_builtin(&self, args: Vector<Type>, scope: &mut Scope) -> Result<Type, Error>;
 }
 
 impl BuiltinCall for BuiltinFunction {
-    fn call_builtin(&mut self, args: Vector<Type>, scope: &mut Scope) -> Type {
+    fn call_builtin(&self, args: Vector<Type>, scope: &mut Scope) -> Result<Type, Error> {
         (self.inner)(args, scope)
     }
 }
@@ -88,64 +164,227 @@ impl PartialEq for BuiltinFunction {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct Function {
-    params: Vector<Type>,
+/// A function body whose variables have been resolved to frame slots.
+///
+/// Evaluating a `Type::Lambda` closes over the current frame and produces a
+/// `Type::Function`.
+#[derive(Debug)]
+pub struct Lambda {
+    name: Option<Symbol>,
+    arity: usize,
+    names: Rc<[Symbol]>,
     body: Vector<Type>,
-    environ: HashMap<String, Type>,
+    chunk: RefCell<Option<Rc<Chunk>>>,
 }
 
-impl Function {
+impl Lambda {
+    /// `names` lists the parameters first, followed by any internal defines.
     pub fn new(
-        params: Vector<Type>,
+        name: Option<Symbol>,
+        arity: usize,
+        names: Rc<[Symbol]>,
         body: Vector<Type>,
-        environ: HashMap<String, Type>,
-    ) -> Function {
-        Function {
-            params,
+    ) -> Lambda {
+        Lambda {
+            name,
+            arity,
+            names,
             body,
-            environ,
+            chunk: RefCell::new(None),
         }
     }
+
+    pub fn name(&self) -> Option<Symbol> {
+        self.name
+    }
+
+    pub fn body(&self) -> &Vector<Type> {
+        &self.body
+    }
+
+    /// The bytecode for the body, compiled on first use.
+    pub fn chunk(
+        &self,
+        compile: impl FnOnce(&Lambda) -> Result<Chunk, Error>,
+    ) -> Result<Rc<Chunk>, Error> {
+        if let Some(chunk) = &*self.chunk.borrow() {
+            return Ok(chunk.clone());
+        }
+
+        let chunk = Rc::new(compile(self)?);
+        *self.chunk.borrow_mut() = Some(chunk.clone());
+
+        Ok(chunk)
+    }
 }
 
-impl Call for Function {
-    fn call(&self, args: Vector<Type>, stg: &mut Scope) -> Type {
-        if args.len() != self.params.len() {
-            panic!("invalid number of arguments");
+impl PartialEq for Lambda {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.arity == other.arity
+            && self.names == other.names
+            && self.body == other.body
+    }
+}
+
+#[derive(Clone)]
+pub struct Function {
+    lambda: Rc<Lambda>,
+    /// The globals the body was resolved against, which may not be the
+    /// caller's when the function comes from another environment.
+    globals: Rc<RefCell<Globals>>,
+    frame: Option<Rc<Frame>>,
+}
+
+impl Function {
+    /// Close `lambda` over the current frame and globals of `scope`.
+    pub fn new(lambda: Rc<Lambda>, scope: &Scope) -> Function {
+        Function {
+            lambda,
+            globals: scope.globals.clone(),
+            frame: scope.frame.clone(),
         }
+    }
+
+    pub fn name(&self) -> Option<Symbol> {
+        self.lambda.name
+    }
 
-        let mut bounded_storage = Scope::new(self.environ.clone());
+    pub fn lambda(&self) -> &Rc<Lambda> {
+        &self.lambda
+    }
 
-        for (key, value) in self.params.iter().zip(args.into_iter()) {
-            bounded_storage.put(key.as_key(), value);
+    /// Bind `args` to the parameters in a fresh frame, returning the scope
+    /// the body runs in.
+    pub fn enter(&self, args: Vector<Type>) -> Result<Scope, Error> {
+        let lambda = &self.lambda;
+
+        if args.len() != lambda.arity {
+            let name = match lambda.name {
+                Some(name) => name.to_string(),
+                None => "#<procedure>".into(),
+            };
+
+            return Err(Error::Arity {
+                name,
+                expected: lambda.arity,
+                got: args.len(),
+            });
         }
 
+        let mut slots: Vec<Type> = args.into_iter().collect();
+        slots.resize(lambda.names.len(), Type::Nil);
+
+        let frame = Frame {
+            names: lambda.names.clone(),
+            slots: RefCell::new(slots),
+            parent: self.frame.clone(),
+        };
+
+        Ok(Scope {
+            globals: self.globals.clone(),
+            frame: Some(gc::alloc(frame)),
+        })
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.lambda.name {
+            Some(name) => write!(f, "Function: {}", name),
+            None => write!(f, "Function: <anonymous>"),
+        }
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        let same_frame = match (&self.frame, &other.frame) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+
+        Rc::ptr_eq(&self.lambda, &other.lambda) && same_frame
+    }
+}
+
+impl Trace for Function {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.edge(&self.globals);
+
+        if let Some(frame) = &self.frame {
+            tracer.edge(frame);
+        }
+    }
+
+    // A function's only edges lead to its frame and its globals, and both
+    // clear the values they hold. Any cycle through a function has to leave
+    // it through one of them, so clearing them breaks the cycle, and the
+    // function is then freed by reference counting.
+    fn clear(&self) {}
+
+    fn size(&self) -> usize {
+        std::mem::size_of::<Function>()
+    }
+}
+
+impl Call for Function {
+    fn call(&self, args: Vector<Type>, _stg: &mut Scope) -> Result<Type, Error> {
+        let mut bounded_storage = self.enter(args)?;
+
         let mut values: Vec<Type> = Vec::new();
 
-        for expr in &self.body {
-            values.push(eval(expr.clone(), &mut bounded_storage));
+        for expr in &self.lambda.body {
+            values.push(eval(expr.clone(), &mut bounded_storage)?);
         }
 
-        values.last().cloned().unwrap_or(Type::Nil)
+        Ok(values.last().cloned().unwrap_or(Type::Nil))
     }
 }
 
 pub struct BuiltinMacro {
     name: String,
-    inner: Box<dyn FnMut(Vector<Type>, &mut Scope) -> Type>,
+    aliases: Vec<String>,
+    doc: Option<String>,
+    inner: Box<BuiltinFn>,
 }
 
 impl BuiltinMacro {
     pub fn new(
         name: String,
-        fun: impl FnMut(Vector<Type>, &mut Scope) -> Type + 'static,
+        fun: impl Fn(Vector<Type>, &mut Scope) -> Result<Type, Error> + 'static,
     ) -> BuiltinMacro {
         BuiltinMacro {
             name,
+            aliases: Vec::new(),
+            doc: None,
             inner: Box::new(fun),
         }
     }
+
+    /// Also bind the builtin as `alias`.
+    pub fn with_alias(mut self, alias: &str) -> BuiltinMacro {
+        self.aliases.push(alias.into());
+        self
+    }
+
+    pub fn with_doc(mut self, doc: &str) -> BuiltinMacro {
+        self.doc = Some(doc.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    pub fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
 }
 
 impl fmt::Debug for BuiltinMacro {
@@ -161,48 +400,383 @@ impl PartialEq for BuiltinMacro {
 }
 
 impl BuiltinCall for BuiltinMacro {
-    fn call_builtin(&mut self, args: Vector<Type>, scope: &mut Scope) -> Type {
+    fn call_builtin(&self, args: Vector<Type>, scope: &mut Scope) -> Result<Type, Error> {
         (self.inner)(args, scope)
     }
 }
 
+/// The activation record of a function call.
+///
+/// Slots are laid out in the order of the owning `Lambda`'s names, so resolved
+/// code reaches a variable through `(depth, index)` alone. The names are kept
+/// for code that still refers to variables by symbol.
+pub struct Frame {
+    names: Rc<[Symbol]>,
+    slots: RefCell<Vec<Type>>,
+    parent: Option<Rc<Frame>>,
+}
+
+impl Frame {
+    pub fn names(&self) -> &Rc<[Symbol]> {
+        &self.names
+    }
+
+    pub fn parent(&self) -> Option<&Rc<Frame>> {
+        self.parent.as_ref()
+    }
+
+    /// The frame `depth` levels out, if there are that many.
+    fn ancestor(&self, depth: usize) -> Option<&Frame> {
+        let mut frame = self;
+
+        for _ in 0..depth {
+            frame = frame.parent.as_ref()?;
+        }
+
+        Some(frame)
+    }
+}
+
+impl Trace for Frame {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Ok(slots) = self.slots.try_borrow() {
+            for value in slots.iter() {
+                tracer.value(value);
+            }
+        }
+
+        if let Some(parent) = &self.parent {
+            tracer.edge(parent);
+        }
+    }
+
+    fn clear(&self) {
+        let slots = match self.slots.try_borrow_mut() {
+            Ok(mut slots) => std::mem::take(&mut *slots),
+            Err(_) => return,
+        };
+
+        drop(slots);
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of::<Frame>() + self.names.len() * std::mem::size_of::<Type>()
+    }
+}
+
+impl fmt::Debug for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Frame: {:?}", self.names)
+    }
+}
+
+impl PartialEq for Frame {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Top-level bindings, stored in a table indexed by slot.
+///
+/// The name to slot map is only consulted when code is resolved, or when
+/// unresolved code refers to a global by name.
+#[derive(Debug, Default, PartialEq)]
+pub struct Globals {
+    names: Vec<Symbol>,
+    values: Vec<Option<Type>>,
+    index: HashMap<Symbol, usize>,
+    requires: Requires,
+    modules: HashMap<Symbol, Rc<Module>>,
+}
+
+impl Globals {
+    /// Get the slot for `name`, allocating an unbound one if needed.
+    pub fn slot(&mut self, name: Symbol) -> usize {
+        if let Some(&slot) = self.index.get(&name) {
+            return slot;
+        }
+
+        let slot = self.values.len();
+
+        self.names.push(name);
+        self.values.push(None);
+        self.index.insert(name, slot);
+
+        slot
+    }
+
+    pub fn get(&self, slot: usize) -> Result<&Type, Error> {
+        match self.values[slot] {
+            Some(ref value) => Ok(value),
+            None => Err(Error::Unbound(self.names[slot])),
+        }
+    }
+
+    /// The value in `slot`, or `None` while it's unbound.
+    pub fn value(&self, slot: usize) -> Option<&Type> {
+        self.values[slot].as_ref()
+    }
+
+    pub fn set(&mut self, slot: usize, value: Type) {
+        self.values[slot] = Some(value);
+    }
+
+    pub fn lookup(&self, name: Symbol) -> Option<&Type> {
+        self.index.get(&name).and_then(|&slot| self.value(slot))
+    }
+
+    pub fn define(&mut self, name: Symbol, value: Type) {
+        let slot = self.slot(name);
+        self.set(slot, value);
+    }
+
+    /// The modules `require`d into these globals.
+    pub fn requires(&mut self) -> &mut Requires {
+        &mut self.requires
+    }
+
+    pub fn module(&self, name: Symbol) -> Option<Rc<Module>> {
+        self.modules.get(&name).cloned()
+    }
+
+    /// Register `module`, replacing any module of the same name.
+    pub fn add_module(&mut self, module: Rc<Module>) {
+        self.modules.insert(module.name(), module);
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &Rc<Module>> {
+        self.modules.values()
+    }
+
+    /// Every bound global with its value, in the order they were allocated.
+    pub fn bindings(&self) -> impl Iterator<Item = (Symbol, &Type)> {
+        self.names
+            .iter()
+            .zip(&self.values)
+            .filter_map(|(name, value)| value.as_ref().map(|value| (*name, value)))
+    }
+}
+
+// Functions point back at the globals they're stored in, so globals are
+// tracked by the collector like frames are.
+impl Trace for RefCell<Globals> {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Ok(globals) = self.try_borrow() {
+            for value in globals.values.iter().flatten() {
+                tracer.value(value);
+            }
+
+            for module in globals.modules.values() {
+                tracer.edge(module.env().globals());
+            }
+        }
+    }
+
+    fn clear(&self) {
+        let (values, modules) = match self.try_borrow_mut() {
+            Ok(mut globals) => {
+                let values: Vec<Option<Type>> =
+                    globals.values.iter_mut().map(Option::take).collect();
+
+                (values, std::mem::take(&mut globals.modules))
+            }
+            Err(_) => return,
+        };
+
+        drop(values);
+        drop(modules);
+    }
+
+    fn size(&self) -> usize {
+        match self.try_borrow() {
+            Ok(globals) => {
+                std::mem::size_of::<Globals>()
+                    + globals.values.len() * std::mem::size_of::<Option<Type>>()
+            }
+            Err(_) => std::mem::size_of::<Globals>(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Scope {
-    environ: HashMap<String, Type>,
-    local: HashMap<String, Type>,
+    globals: Rc<RefCell<Globals>>,
+    frame: Option<Rc<Frame>>,
 }
 
 impl Scope {
-    pub fn new(environ: HashMap<String, Type>) -> Scope {
+    pub fn new(environ: HashMap<Symbol, Type>) -> Scope {
+        let mut globals = Globals::default();
+
+        for (key, value) in environ {
+            globals.define(key, value);
+        }
+
         Scope {
-            environ,
-            local: HashMap::new(),
+            globals: gc::alloc(RefCell::new(globals)),
+            frame: None,
         }
     }
 
-    pub fn put(&mut self, key: String, value: Type) {
-        self.local.insert(key, value);
+    pub fn globals(&self) -> &Rc<RefCell<Globals>> {
+        &self.globals
     }
 
-    pub fn fork(&self) -> HashMap<String, Type> {
-        let mut environ = self.environ.clone();
-        environ.extend(self.local.clone());
+    pub fn frame(&self) -> Option<&Rc<Frame>> {
+        self.frame.as_ref()
+    }
 
-        environ
+    /// The top-level scope sharing these globals.
+    pub fn top(&self) -> Scope {
+        Scope {
+            globals: self.globals.clone(),
+            frame: None,
+        }
+    }
+
+    /// Every name visible here: the variables of each enclosing frame,
+    /// innermost first, then the bound globals.
+    pub fn names(&self) -> Vec<Symbol> {
+        let mut names = Vec::new();
+        let mut frame = self.frame.as_ref();
+
+        while let Some(current) = frame {
+            names.extend(current.names.iter().copied());
+            frame = current.parent.as_ref();
+        }
+
+        names.extend(self.globals.borrow().bindings().map(|(name, _)| name));
+        names
+    }
+
+    /// Bind `key` in the innermost frame that declares it, or as a global.
+    pub fn put(&mut self, key: Symbol, value: Type) {
+        if let Some(frame) = &self.frame {
+            if let Some(index) = frame.names.iter().position(|name| *name == key) {
+                frame.slots.borrow_mut()[index] = value;
+                return;
+            }
+        }
+
+        self.globals.borrow_mut().define(key, value);
+    }
+
+    /// Look up a variable by name, walking the frames before the globals.
+    pub fn get(&self, key: Symbol) -> Result<Type, Error> {
+        let mut frame = self.frame.as_ref();
+
+        while let Some(current) = frame {
+            if let Some(index) = current.names.iter().position(|name| *name == key) {
+                return Ok(current.slots.borrow()[index].clone());
+            }
+
+            frame = current.parent.as_ref();
+        }
+
+        match self.globals.borrow().lookup(key) {
+            Some(value) => Ok(value.clone()),
+            None => Err(Error::Unbound(key)),
+        }
+    }
+
+    pub fn local(&self, depth: usize, index: usize) -> Result<Type, Error> {
+        let frame = self.local_frame(depth, index)?;
+        let value = frame.slots.borrow()[index].clone();
+
+        Ok(value)
+    }
+
+    pub fn set_local(&mut self, depth: usize, index: usize, value: Type) -> Result<(), Error> {
+        self.local_frame(depth, index)?.slots.borrow_mut()[index] = value;
+
+        Ok(())
+    }
+
+    /// The frame holding the local at `(depth, index)`. A resolved form run
+    /// somewhere other than where it was resolved may not have one.
+    fn local_frame(&self, depth: usize, index: usize) -> Result<&Frame, Error> {
+        self.frame
+            .as_deref()
+            .and_then(|frame| frame.ancestor(depth))
+            .filter(|frame| index < frame.slots.borrow().len())
+            .ok_or_else(|| {
+                let local = Type::Local(depth, index);
+                Error::Message(format!("{:#} is out of scope", local))
+            })
+    }
+
+    pub fn global(&self, slot: usize) -> Result<Type, Error> {
+        self.globals.borrow().get(slot).cloned()
     }
 }
 
-impl Index<&String> for Scope {
-    type Output = Type;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::eval::create_env;
+    use crate::lexer::Lexer;
+    use crate::parser::parse;
+
+    #[test]
+    fn clone_shares_payloads() {
+        let code = "(define (adder x) (define (add y) (+ x y)) add) (define add2 (adder 2))";
+        let mut lexer = Lexer::new(code);
+        let mut env = create_env();
+
+        for expr in parse(&mut lexer) {
+            eval(expr.unwrap(), &mut env).unwrap();
+        }
+
+        let add2 = env.get(Symbol::intern("add2")).unwrap();
+
+        match (&add2, &add2.clone()) {
+            (Type::Function(a), Type::Function(b)) => {
+                assert!(Rc::ptr_eq(a, b));
+                assert!(Rc::ptr_eq(
+                    a.frame.as_ref().unwrap(),
+                    b.frame.as_ref().unwrap()
+                ));
+            }
+            t => panic!("expected functions, got {:?}", t),
+        }
 
-    #[inline]
-    fn index(&self, key: &String) -> &Type {
-        if self.local.contains_key(key) {
-            self.local.get(key).unwrap()
-        } else if self.environ.contains_key(key) {
-            self.environ.get(key).unwrap()
-        } else {
-            panic!("key not found: {}", key)
+        let string = Type::StrLit("hello".into());
+
+        match (&string, &string.clone()) {
+            (Type::StrLit(a), Type::StrLit(b)) => assert!(Rc::ptr_eq(a, b)),
+            t => panic!("expected strings, got {:?}", t),
         }
     }
+
+    #[test]
+    fn locals_out_of_scope() {
+        let mut env = create_env();
+        let out_of_scope = |depth, index| {
+            let local = Type::Local(depth, index);
+            Err::<Type, _>(Error::Message(format!("{:#} is out of scope", local)))
+        };
+
+        assert_eq!(env.local(0, 0), out_of_scope(0, 0));
+        assert_eq!(
+            env.set_local(0, 0, Type::Nil),
+            out_of_scope(0, 0).map(|_| ())
+        );
+        assert_eq!(eval(Type::Local(1, 0), &mut env), out_of_scope(1, 0));
+
+        let fun = Function::new(
+            Rc::new(Lambda::new(
+                None,
+                1,
+                vec![Symbol::intern("x")].into(),
+                Vector::new(),
+            )),
+            &env,
+        );
+        let inner = fun.enter(Vector::unit(Type::Int(1))).unwrap();
+
+        assert_eq!(inner.local(0, 0), Ok(Type::Int(1)));
+        assert_eq!(inner.local(0, 1), out_of_scope(0, 1));
+        assert_eq!(inner.local(1, 0), out_of_scope(1, 0));
+    }
 }
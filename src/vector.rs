@@ -0,0 +1,230 @@
+//! Vectors: mutable sequences with constant time indexing.
+//!
+//! A vector holds an `im::Vector` of its own. The collector counts the
+//! elements as owned by the vector, so that storage is never shared: values
+//! go in and come out as copies. Vectors read as `#(1 2 3)`, with the elements
+//! taken as data, the way `quote` takes them. The builtins refuse to modify a
+//! literal, as with hash tables (see `hash`).
+
+use crate::convert::FromLisp;
+use crate::error::Error;
+use crate::gc::{self, Trace, Tracer};
+use crate::types::Type;
+
+use im::Vector;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+pub struct VectorCell {
+    elems: RefCell<Vector<Type>>,
+    literal: bool,
+}
+
+impl VectorCell {
+    /// A tracked vector holding `elems`.
+    pub fn new(elems: Vector<Type>) -> Rc<VectorCell> {
+        VectorCell::alloc(elems, false)
+    }
+
+    /// A tracked vector read from a `#(...)` literal.
+    pub fn literal(elems: Vector<Type>) -> Rc<VectorCell> {
+        VectorCell::alloc(elems, true)
+    }
+
+    fn alloc(elems: Vector<Type>, literal: bool) -> Rc<VectorCell> {
+        gc::alloc(VectorCell {
+            elems: RefCell::new(elems.iter().cloned().collect()),
+            literal,
+        })
+    }
+
+    /// Whether the vector was read from a literal, and shouldn't be modified.
+    pub fn is_literal(&self) -> bool {
+        self.literal
+    }
+
+    pub fn len(&self) -> usize {
+        self.elems.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elems.borrow().is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Type> {
+        self.elems.borrow().get(index).cloned()
+    }
+
+    /// Set the element at `index`, returning `false` if it's out of range.
+    pub fn set(&self, index: usize, value: Type) -> bool {
+        match self.elems.borrow_mut().get_mut(index) {
+            Some(elem) => {
+                *elem = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn fill(&self, value: Type) {
+        let len = self.len();
+        *self.elems.borrow_mut() = Vector::from(vec![value; len]);
+    }
+
+    /// A copy of the elements, not sharing storage with the vector.
+    pub fn elems(&self) -> Vector<Type> {
+        self.elems.borrow().iter().cloned().collect()
+    }
+}
+
+/// Vectors are equal if their elements are.
+impl PartialEq for VectorCell {
+    fn eq(&self, other: &VectorCell) -> bool {
+        gc::equal(self, other, || {
+            *self.elems.borrow() == *other.elems.borrow()
+        })
+    }
+}
+
+impl fmt::Debug for VectorCell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Vector {:?}", self.elems.borrow())
+    }
+}
+
+impl Trace for VectorCell {
+    fn trace(&self, tracer: &mut Tracer) {
+        for elem in self.elems.borrow().iter() {
+            tracer.value(elem);
+        }
+    }
+
+    fn clear(&self) {
+        *self.elems.borrow_mut() = Vector::new();
+    }
+
+    fn size(&self) -> usize {
+        mem::size_of::<VectorCell>() + self.len() * mem::size_of::<Type>()
+    }
+}
+
+impl FromLisp for Rc<VectorCell> {
+    fn from_lisp(value: Type) -> Result<Rc<VectorCell>, Error> {
+        match value {
+            Type::Vector(vector) => Ok(vector),
+            got => Err(Error::Conversion {
+                expected: "a vector",
+                got,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::testing::written;
+    use crate::eval::{create_env, eval_str};
+
+    #[test]
+    fn literal() {
+        assert_eq!(
+            written("#(1 \"two\" (3 4))"),
+            Ok("#(1 \"two\" (3 4))".into())
+        );
+        assert_eq!(written("'#(a b)"), Ok("#(a b)".into()));
+        assert_eq!(written("#()"), Ok("#()".into()));
+        assert_eq!(
+            written("(equal? #(1 (2)) (vector 1 '(2)))"),
+            Ok("#t".into())
+        );
+    }
+
+    #[test]
+    fn builtins() {
+        let code = "
+            (define v (make-vector 3 0))
+            (vector-set! v 1 'x)
+            v";
+
+        assert_eq!(written(code), Ok("#(0 x 0)".into()));
+        assert_eq!(written("(vector-ref (vector 1 2 3) 2)"), Ok("3".into()));
+        assert_eq!(written("(vector-length (make-vector 4))"), Ok("4".into()));
+        assert_eq!(written("(vector->list #(1 2))"), Ok("(1 2)".into()));
+        assert_eq!(written("(vector->list #())"), Ok("()".into()));
+        assert_eq!(written("(list->vector '(1 2))"), Ok("#(1 2)".into()));
+        assert_eq!(
+            written("(define v (vector 1 2)) (vector-fill! v 7) v"),
+            Ok("#(7 7)".into())
+        );
+        assert_eq!(
+            written("(define (square x) (* x x)) (vector-map square #(1 2 3))"),
+            Ok("#(1 4 9)".into())
+        );
+        assert_eq!(
+            written("(vector-map + #(1 2 3) #(10 20))"),
+            Ok("#(11 22)".into())
+        );
+    }
+
+    #[test]
+    fn self_containing() {
+        let code = "
+            (define v (make-vector 2 0))
+            (vector-set! v 0 v)
+            (define w (make-vector 2 0))
+            (vector-set! w 0 w)
+            (define t (make-hash-table))
+            (hash-set! t v 1)";
+        let mut env = create_env();
+        eval_str(code, &mut env).unwrap();
+
+        let mut eval = |code: &str| format!("{:#}", eval_str(code, &mut env).unwrap());
+
+        assert_eq!(eval("v"), "#(#(...) 0)");
+        assert_eq!(eval("(equal? v w)"), "#t");
+        assert_eq!(eval("(hash-ref t v)"), "1");
+        assert_eq!(eval("(hash-ref t w)"), "1");
+    }
+
+    #[test]
+    fn collect_around_escaped_elements() {
+        let code = "
+            (define (mk) (define x 42) (define (get) x) get)
+            (define (leak)
+                (define v (vector (mk) 0))
+                (vector-set! v 1 v)
+                (vector->list v))
+            (define kept (vector (leak) (gc)))
+            (define escaped (list->vector (vector-ref kept 0)))
+            ((vector-ref escaped 0))";
+
+        assert_eq!(written(code), Ok("42".into()));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            written("(vector-ref #(1 2) 2)"),
+            Err("vector-ref: index 2 out of range for a vector of length 2".into())
+        );
+        assert_eq!(
+            written("(vector-set! (vector 1 2) -1 0)"),
+            Err("vector-set!: expected an integer that fits in usize, got -1".into())
+        );
+        assert_eq!(
+            written("(vector-length '(1 2))"),
+            Err("vector-length: expected a vector, got (1 2)".into())
+        );
+        assert_eq!(
+            written("(define (fresh) #(0 0)) (vector-set! (fresh) 0 9)"),
+            Err("vector-set!: can't modify a literal vector".into())
+        );
+        assert_eq!(
+            written("(vector-fill! '#(1) 0)"),
+            Err("vector-fill!: can't modify a literal vector".into())
+        );
+    }
+}
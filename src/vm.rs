@@ -0,0 +1,87 @@
+//! A stack machine that runs the bytecode from `compile`.
+//!
+//! The VM shares `Scope`, frames and function values with the tree-walking
+//! evaluator in `eval`, which stays around as the reference implementation.
+//! Either backend can call functions created by the other.
+
+use crate::compile::{Chunk, Compiler, Op};
+use crate::error::Error;
+use crate::eval;
+use crate::gc;
+use crate::resolve::resolve_in;
+use crate::types::{BuiltinCall, Function, Scope, Type};
+
+use im::Vector;
+
+/// Resolve, compile and run a top-level form.
+pub fn eval(expr: Type, stg: &mut Scope) -> Result<Type, Error> {
+    let expr = resolve_in(expr, stg)?;
+    let chunk = Compiler::new(&stg.globals().borrow()).expr(expr)?;
+
+    run(&chunk, stg)
+}
+
+/// Call any callable value with already evaluated arguments.
+pub fn call(callee: Type, args: Vector<Type>, stg: &mut Scope) -> Result<Type, Error> {
+    match callee {
+        Type::Function(fun) => call_function(&fun, args),
+        Type::Builtin(builtin) => builtin.call_builtin(args, stg),
+        Type::Native(native) => native.call(args, stg),
+        _ => Err(Error::NotCallable(callee)),
+    }
+}
+
+fn call_function(fun: &Function, args: Vector<Type>) -> Result<Type, Error> {
+    let mut stg = fun.enter(args)?;
+    let chunk = fun
+        .lambda()
+        .chunk(|lambda| Compiler::new(&stg.globals().borrow()).lambda(lambda))?;
+
+    run(&chunk, &mut stg)
+}
+
+pub fn run(chunk: &Chunk, stg: &mut Scope) -> Result<Type, Error> {
+    let mut stack: Vec<Type> = Vec::new();
+
+    for op in &chunk.code {
+        match *op {
+            Op::Const(index) => stack.push(chunk.consts[index].clone()),
+            Op::Local(depth, index) => stack.push(stg.local(depth, index)?),
+            Op::Global(slot) => stack.push(stg.global(slot)?),
+            Op::SetLocal(depth, index) => {
+                let value = stack.pop().unwrap();
+                stg.set_local(depth, index, value)?;
+                stack.push(Type::Nil);
+            }
+            Op::SetGlobal(slot) => {
+                let value = stack.pop().unwrap();
+                stg.globals().borrow_mut().set(slot, value);
+                stack.push(Type::Nil);
+            }
+            Op::Closure(index) => match &chunk.consts[index] {
+                Type::Lambda(lambda) => {
+                    let fun = Function::new(lambda.clone(), stg);
+                    stack.push(Type::Function(gc::alloc(fun)));
+                }
+                other => {
+                    return Err(Error::Message(format!(
+                        "cannot close over {:#}, which isn't a lambda",
+                        other
+                    )))
+                }
+            },
+            Op::Call(argc) => {
+                let args = stack.split_off(stack.len() - argc).into_iter().collect();
+                let callee = stack.pop().unwrap();
+
+                stack.push(call(callee, args, stg)?);
+            }
+            Op::Eval(index) => stack.push(eval::eval(chunk.consts[index].clone(), stg)?),
+            Op::Pop => {
+                stack.pop();
+            }
+        }
+    }
+
+    Ok(stack.pop().unwrap_or(Type::Nil))
+}
@@ -0,0 +1,85 @@
+use rslisp::{lexer::Lexer, parser::parse, types::Type, Backend, Interpreter};
+
+/// Programs run through both the tree-walking evaluator and the VM.
+const PROGRAMS: &[&str] = &[
+    "1 2.5 \"str\" ()",
+    "(+ 1 2) (* 2 3) (+ 1 (* 2 3) 4.5)",
+    "'sym '(1 2 (3 sym)) ''nested",
+    "(define x 5) x (+ x x)",
+    "(define (add-new x y) (+ x y)) (add-new 1 1)",
+    "(define (scaled x) (* x factor)) (define factor 3) (scaled 4)",
+    "(define (adder x) (define (add y) (+ x y)) add) (define add2 (adder 2)) (add2 3)",
+    "(define (f x) (define y (* x 2)) (define z (+ y 1)) (+ x y z)) (f 3)",
+    "(define (outer a) (define (mid b) (define (inner c) (+ a b c)) inner) mid) (((outer 1) 2) 3)",
+    "(define (shadow +) (* + 2)) (shadow 4)",
+    "(define (nothing)) (nothing)",
+    "(define (apply-twice f x) (f (f x))) (define (double x) (* 2 x)) (apply-twice double 5)",
+    "(module m (export f) (define (g x) (* x 2)) (define (f x) (+ (g x) 1))) (import (prefix m m:)) (m:f 3) (import (only m f)) (f 4)",
+    "(define-record-type <point> (make-point x y) point? (x point-x set-point-x!) (y point-y)) (define p (make-point 1 2)) (set-point-x! p 5) (point-x p) (point-y p) (point? p) (point? 5) (equal? p (make-point 5 2))",
+    "(define t (make-hash-table)) (hash-set! t 'a 1) (hash-set! t '(1 2) 2) (hash-ref t '(1 2)) (hash-ref t 'b 0) (hash-remove! t 'a) (hash-count t) (hash-ref #hash((a . 1)) 'a) (equal? #hash((a . 1)) #hash((a . 1)))",
+    "(define v (make-vector 3 0)) (vector-set! v 1 'x) (vector-ref v 1) (vector-length v) (vector->list v) (list->vector '(1 2)) (vector-map + #(1 2 3) #(10 20)) (equal? #(1 (2)) (vector 1 '(2)))",
+    "(format \"~a is ~s~%\" 'x \"y\") (format \"[~5a|~-5a]\" 'ab 'cd) (format \"~08.3d ~04x\" -1.5 255) (string-append (substring \"héllo\" 1 3) (number->string 4.5))",
+    "unbound (define (f) missing) (f) (define missing 1) (f)",
+    "(define (two x y) x) (two 1) (two 1 2 3) (two 1 2) (vector-ref #(1) 0 0)",
+    "(+ 1 \"a\") (* 'x 2) (vector-ref '(1) 0) (hash-ref 5 'a) (string-length 1)",
+    "(+ 9223372036854775807 1) (1 2) ((define x 1)) (format \"~q\")",
+    "(define) (define x) (define x 1 2) (define (f) (define 1 2)) (f) (define 1 2)",
+];
+
+/// The result of each expression in `code`, errors included, run on
+/// `backend`.
+fn results(backend: Backend, code: &str) -> Vec<Result<Type, String>> {
+    let mut lexer = Lexer::new(code);
+    let mut interp = Interpreter::with_backend(backend);
+
+    parse(&mut lexer)
+        .map(|expr| interp.eval(expr.unwrap()).map_err(|err| err.to_string()))
+        .collect()
+}
+
+fn tree_walk(code: &str) -> Vec<Result<Type, String>> {
+    results(Backend::TreeWalk, code)
+}
+
+fn bytecode(code: &str) -> Vec<Result<Type, String>> {
+    results(Backend::Bytecode, code)
+}
+
+#[test]
+fn backends_agree() {
+    for program in PROGRAMS {
+        assert_eq!(tree_walk(program), bytecode(program), "{}", program);
+    }
+}
+
+#[test]
+fn vm_results() {
+    assert_eq!(
+        bytecode("(define (add-new x y) (+ x y)) (add-new 1 1)")[1],
+        Ok(Type::Int(2))
+    );
+    assert_eq!(
+        bytecode("(define (outer a) (define (mid b) (define (inner c) (+ a b c)) inner) mid) (((outer 1) 2) 3)")[1],
+        Ok(Type::Int(6))
+    );
+}
+
+#[test]
+fn vm_errors() {
+    assert_eq!(
+        bytecode("(define (f) missing) (f)")[1],
+        Err("unbound variable: missing".into())
+    );
+    assert_eq!(
+        bytecode("(define (two x y) x) (two 1)")[1],
+        Err("two: expected 2 arguments, got 1".into())
+    );
+    assert_eq!(
+        bytecode("(+ 1 \"a\")")[0],
+        Err("+: expected a number, got \"a\"".into())
+    );
+    assert_eq!(
+        bytecode("(define (f) (define 1 2)) (f)")[0],
+        Err("syntax error: expected a name, got 1".into())
+    );
+}
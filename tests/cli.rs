@@ -0,0 +1,67 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn rslisp(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rslisp"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn run_expression() {
+    let output = rslisp(&["-e", "(print (+ 1 2)) (print *argv*)", "a", "b"], "");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "3\n(a b)\n");
+}
+
+#[test]
+fn run_file_with_shebang() {
+    let path = std::env::temp_dir().join(format!("rslisp-cli-{}.lisp", std::process::id()));
+    fs::write(&path, "#!/usr/bin/env rslisp\n(write *argv*)\n").unwrap();
+
+    let output = rslisp(&[path.to_str().unwrap(), "x"], "");
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "(\"x\")");
+}
+
+#[test]
+fn run_stdin() {
+    let output = rslisp(&["-"], "(define (sq x) (* x x))\n(print (sq 7))\n");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "49\n");
+}
+
+#[test]
+fn exit_status() {
+    assert_eq!(rslisp(&["-e", "(exit 7)"], "").status.code(), Some(7));
+    assert_eq!(rslisp(&["-e", "(exit)"], "").status.code(), Some(0));
+
+    let failed = rslisp(&["-e", "(print 1) (undefined) (print 2)"], "");
+    assert_eq!(failed.status.code(), Some(1));
+    assert_eq!(stdout(&failed), "1\n");
+    assert!(String::from_utf8_lossy(&failed.stderr).contains("unbound variable: undefined"));
+
+    assert_eq!(rslisp(&["missing.lisp"], "").status.code(), Some(2));
+}
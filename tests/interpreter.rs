@@ -0,0 +1,80 @@
+use rslisp::{Error, Interpreter, Value};
+
+use std::fs;
+
+#[test]
+fn eval_and_globals() {
+    let mut interp = Interpreter::new();
+
+    interp.define_global("base", Value::Int(10));
+    assert_eq!(
+        interp.eval_str("(define (offset x) (+ base x)) (offset 5)"),
+        Ok(Value::Int(15))
+    );
+    assert_eq!(interp.get_global("base"), Some(Value::Int(10)));
+    assert_eq!(interp.get_global("missing"), None);
+}
+
+#[test]
+fn call_by_name() {
+    let mut interp = Interpreter::new();
+    interp.eval_str("(define (area w h) (* w h))").unwrap();
+
+    assert_eq!(
+        interp.call("area", vec![Value::Int(3), Value::Int(4)]),
+        Ok(Value::Int(12))
+    );
+    assert_eq!(interp.call("+", vec![Value::Int(1)]), Ok(Value::Int(1)));
+    assert_eq!(
+        interp.call("nope", vec![]),
+        Err(Error::Unbound("nope".into()))
+    );
+    assert!(interp.call("area", vec![Value::Int(3)]).is_err());
+}
+
+#[test]
+fn eval_file() {
+    let path = std::env::temp_dir().join(format!("rslisp-interp-{}.lisp", std::process::id()));
+    fs::write(&path, "(define answer 42) (+ answer 0)").unwrap();
+
+    let mut interp = Interpreter::new();
+    let result = interp.eval_file(&path);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(result, Ok(Value::Int(42)));
+    assert_eq!(interp.get_global("answer"), Some(Value::Int(42)));
+}
+
+#[test]
+fn register_typed_closures() {
+    let mut interp = Interpreter::new();
+
+    interp.register("clamp", |x: f64, lo: f64, hi: f64| -> f64 {
+        x.max(lo).min(hi)
+    });
+    interp.register("shout", |s: String| s.to_uppercase());
+    interp.register("halve", |n: i64| -> Result<i64, Error> {
+        if n % 2 == 0 {
+            Ok(n / 2)
+        } else {
+            Err(Error::Message(format!("{} is odd", n)))
+        }
+    });
+
+    assert_eq!(interp.eval_str("(clamp 12 0 10)"), Ok(Value::Float(10.0)));
+    assert_eq!(
+        interp.eval_str("(shout \"hi\")"),
+        Ok(Value::StrLit("HI".into()))
+    );
+    assert_eq!(interp.eval_str("(halve 8)"), Ok(Value::Int(4)));
+    assert_eq!(
+        interp.eval_str("(halve 3)"),
+        Err(Error::Message("3 is odd".into()))
+    );
+
+    let err = interp.eval_str("(clamp 1 \"low\" 10)").unwrap_err();
+    assert_eq!(err.to_string(), "clamp: expected a number, got \"low\"");
+
+    let err = interp.eval_str("(clamp 1)").unwrap_err();
+    assert_eq!(err.to_string(), "clamp: expected 3 arguments, got 1");
+}
@@ -0,0 +1,100 @@
+use proptest::prelude::*;
+
+use rslisp::{
+    eval::quote, hash::HashTable, parser, printer, symbol::Symbol, types::Type, vector::VectorCell,
+};
+
+use std::rc::Rc;
+
+fn read_all(code: &str) -> Vec<Type> {
+    parser::read(code).unwrap()
+}
+
+/// Any value the reader can produce. Pairs, vectors and tables hold data,
+/// so lists inside them are `ConsList`s.
+fn readable() -> impl Strategy<Value = Type> {
+    let leaf = prop_oneof![
+        any::<i64>().prop_map(Type::Int),
+        any::<f64>()
+            .prop_filter("floats must be finite", |f| f.is_finite())
+            .prop_map(Type::Float),
+        any::<String>().prop_map(|s| Type::StrLit(s.into())),
+        any::<String>().prop_map(|s| Type::Symbol(Symbol::intern(&s))),
+        "[a-z+*/<>=!?-]{1,8}".prop_map(|s| Type::Symbol(Symbol::intern(&s))),
+        any::<bool>().prop_map(Type::Bool),
+        Just(Type::Nil),
+    ];
+
+    leaf.prop_recursive(4, 32, 6, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 1..6)
+                .prop_map(|items| Type::SExpr(items.into_iter().collect())),
+            inner
+                .clone()
+                .prop_map(|quoted| Type::Quoted(Rc::new(quoted))),
+            (inner.clone(), inner.clone())
+                .prop_map(|(car, cdr)| Type::Cons(vec![quote(car), quote(cdr)].into())),
+            prop::collection::vec(inner.clone(), 0..6).prop_map(|elems| {
+                Type::Vector(VectorCell::literal(elems.into_iter().map(quote).collect()))
+            }),
+            prop::collection::vec((inner.clone(), inner), 0..4).prop_map(|entries| {
+                let entries = entries
+                    .into_iter()
+                    .map(|(key, value)| (quote(key), quote(value)));
+
+                Type::HashMap(HashTable::literal(entries))
+            }),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn write_then_read(value in readable()) {
+        let written = printer::write(&value);
+
+        prop_assert_eq!(read_all(&written), vec![value], "written as {}", written);
+    }
+
+    #[test]
+    fn write_then_read_sequence(values in prop::collection::vec(readable(), 0..4)) {
+        let written: Vec<String> = values.iter().map(printer::write).collect();
+
+        prop_assert_eq!(read_all(&written.join(" ")), values);
+    }
+}
+
+#[test]
+fn round_trip_examples() {
+    let examples = [
+        r#"(define (add-new x y) (+ x y))"#,
+        r#""tab\tquote\"backslash\\""#,
+        r#"'(1 -2 3.5 |two words| ||)"#,
+        r#"''nested"#,
+        r#"(-1.0 +.5 -.25)"#,
+        r#"'((a . 1) (|.| . (b c)))"#,
+    ];
+
+    for code in examples.iter() {
+        let values = read_all(code);
+        let written: Vec<String> = values.iter().map(printer::write).collect();
+
+        assert_eq!(read_all(&written.join(" ")), values, "{}", code);
+    }
+}
+
+#[test]
+fn dotted_pairs() {
+    let pair = |car, cdr| Type::Cons(vec![car, cdr].into_iter().collect());
+    let sym = |name| Type::Symbol(Symbol::intern(name));
+
+    assert_eq!(read_all("(a . 1)"), vec![pair(sym("a"), Type::Int(1))]);
+    assert_eq!(
+        read_all("(|.| . 0.5)"),
+        vec![pair(sym("."), Type::Float(0.5))]
+    );
+
+    for code in ["(a . b . c)", "(a b . c)", "(. a)", "#(1 . 2)", ".", "'."].iter() {
+        assert!(parser::read(code).is_err(), "{}", code);
+    }
+}